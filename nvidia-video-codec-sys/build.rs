@@ -52,6 +52,10 @@ fn main() {
     //println!("cargo:rustc-link-lib=dylib={}", "nvidia-encode");
     println!("cargo:rustc-link-lib=dylib={}", "nppc");
     println!("cargo:rustc-link-lib=dylib={}", "nppicc");
+    println!("cargo:rustc-link-lib=dylib={}", "nppidei");
+    println!("cargo:rustc-link-lib=dylib={}", "nppif");
+    println!("cargo:rustc-link-lib=dylib={}", "nppig");
+    println!("cargo:rustc-link-lib=dylib={}", "nppist");
     println!(r"cargo:rustc-link-search=/usr/local/cuda/lib64");
 
     let cuda_builder = common_builder()
@@ -85,6 +89,16 @@ fn main() {
             cuda_include
                 .join("nppi_data_exchange_and_initialization.h")
                 .to_string_lossy(),
+        )
+        .header(
+            cuda_include
+                .join("nppi_statistics_functions.h")
+                .to_string_lossy(),
+        )
+        .header(
+            cuda_include
+                .join("nppi_filtering_functions.h")
+                .to_string_lossy(),
         );
 
     format_write(npp_builder, "src/npp.rs");
@@ -0,0 +1,268 @@
+//! Converting frames to packed RGB in host memory on several threads.
+//!
+//! NPP keeps a single stream for the whole process and CUDA a context
+//! stack per thread, so converting from several threads with the plain
+//! functions of the crate serializes them at best. The pool instead gives
+//! every worker its own stream, NPP stream context and pinned staging
+//! buffer, and keeps the context current on the workers.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use nvidia_video_codec::convert::{ConvertPool, PixelFormat};
+//! # fn run(context: Arc<nvidia_video_codec::cuda::context::CuContext>,
+//! #        frames: Vec<Arc<nvidia_video_codec::cuvid::DeviceFrame>>) {
+//! let pool = ConvertPool::new(context, 4, PixelFormat::Rgb24).unwrap();
+//! let pending: Vec<_> = frames.into_iter().map(|frame| pool.submit(frame)).collect();
+//! for result in pending {
+//!     let image = result.recv().unwrap().unwrap();
+//! }
+//! # }
+//! ```
+
+use std::os::raw::c_void;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use super::{ffi, CudaResult, NppResult};
+use cuda::context::CuContext;
+use cuda::mem::DeviceBuffer;
+use cuvid::DeviceFrame;
+use quality::Error;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PixelFormat {
+    Rgb24,
+    Bgr24,
+}
+
+/// A frame converted to tightly packed 8 bit samples, 3 per pixel.
+#[derive(Clone, Debug)]
+pub struct ConvertedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: i64,
+    pub data: Vec<u8>,
+}
+
+struct Job {
+    frame: Arc<DeviceFrame>,
+    result: flume::Sender<Result<ConvertedFrame, Error>>,
+}
+
+/// Worker threads converting `DeviceFrame`s of one context in parallel.
+///
+/// Frames are handed out to the first idle worker, so results of different
+/// submissions can complete out of order. Dropping the pool finishes the
+/// submitted frames first.
+pub struct ConvertPool {
+    sender: Option<flume::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ConvertPool {
+    /// Starts `workers` threads converting frames allocated in `context`.
+    pub fn new(
+        context: Arc<CuContext>,
+        workers: usize,
+        format: PixelFormat,
+    ) -> Result<Self, Error> {
+        let (sender, receiver) = flume::unbounded::<Job>();
+        let mut pool = ConvertPool {
+            sender: Some(sender),
+            workers: Vec::with_capacity(workers),
+        };
+
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let (ready, started) = flume::bounded(1);
+            let context = Arc::clone(&context);
+
+            pool.workers.push(std::thread::spawn(move || {
+                let mut worker = match Worker::new(&context, format) {
+                    Ok(worker) => {
+                        let _ = ready.send(Ok(()));
+                        worker
+                    }
+                    Err(err) => {
+                        let _ = ready.send(Err(err));
+                        return;
+                    }
+                };
+
+                for job in receiver.iter() {
+                    let _ = job.result.send(worker.convert(&job.frame));
+                }
+            }));
+
+            // Dropping the pool on error stops the workers already started.
+            started.recv().unwrap_or(Err(Error::Cuda(
+                ffi::cuda::cudaError_enum_CUDA_ERROR_UNKNOWN,
+            )))?;
+        }
+
+        Ok(pool)
+    }
+
+    /// Queues `frame` for conversion. Its result arrives on the returned
+    /// channel, which can also be awaited with `recv_async`.
+    pub fn submit(
+        &self,
+        frame: Arc<DeviceFrame>,
+    ) -> flume::Receiver<Result<ConvertedFrame, Error>> {
+        let (result, receiver) = flume::bounded(1);
+        let job = Job { frame, result };
+        // The workers only stop once the sender is dropped.
+        let _ = self.sender.as_ref().unwrap().send(job);
+
+        receiver
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl Drop for ConvertPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Page locked host memory, for asynchronous copies from the device.
+struct PinnedBuffer {
+    ptr: *mut c_void,
+    size: usize,
+}
+
+impl PinnedBuffer {
+    fn new(size: usize) -> Result<Self, Error> {
+        let mut ptr = std::ptr::null_mut();
+        unsafe {
+            ffi::cuda::cuMemAllocHost_v2(&mut ptr, size.max(1) as _).err()?;
+        }
+
+        Ok(PinnedBuffer { ptr, size })
+    }
+
+    fn as_slice(&self, len: usize) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, len.min(self.size)) }
+    }
+}
+
+impl Drop for PinnedBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::cuda::cuMemFreeHost(self.ptr);
+        }
+    }
+}
+
+/// The state of one worker thread, which keeps the context current for as
+/// long as it lives.
+struct Worker {
+    format: PixelFormat,
+    stream: ffi::cuda::CUstream,
+    stream_ctx: ffi::npp::NppStreamContext,
+    device: Option<DeviceBuffer>,
+    host: Option<PinnedBuffer>,
+}
+
+impl Worker {
+    fn new(context: &CuContext, format: PixelFormat) -> Result<Self, Error> {
+        unsafe {
+            ffi::cuda::cuCtxPushCurrent_v2(context.context).err()?;
+        }
+        let mut worker = Worker {
+            format,
+            stream: std::ptr::null_mut(),
+            stream_ctx: unsafe { std::mem::zeroed() },
+            device: None,
+            host: None,
+        };
+
+        unsafe {
+            ffi::cuda::cuStreamCreate(
+                &mut worker.stream,
+                ffi::cuda::CUstream_flags_enum_CU_STREAM_NON_BLOCKING,
+            )
+            .err()?;
+            // A context of our own instead of the process wide NPP stream.
+            let mut stream_ctx = std::mem::MaybeUninit::uninit();
+            ffi::npp::nppGetStreamContext(stream_ctx.as_mut_ptr()).err()?;
+            worker.stream_ctx = stream_ctx.assume_init();
+        }
+        worker.stream_ctx.hStream = worker.stream as _;
+
+        Ok(worker)
+    }
+
+    fn convert(&mut self, frame: &DeviceFrame) -> Result<ConvertedFrame, Error> {
+        let pitch = frame.width * 3;
+        let size = (pitch * frame.height) as usize;
+        if self
+            .device
+            .as_ref()
+            .is_none_or(|buffer| buffer.len() < size)
+        {
+            self.device = Some(DeviceBuffer::new(size)?);
+        }
+        if self.host.as_ref().is_none_or(|buffer| buffer.size < size) {
+            self.host = Some(PinnedBuffer::new(size)?);
+        }
+        let device = self.device.as_ref().unwrap();
+        let host = self.host.as_ref().unwrap();
+
+        let src = [
+            frame.ptr() as *const ffi::npp::Npp8u,
+            frame.chroma_ptr() as *const ffi::npp::Npp8u,
+        ];
+        let roi = ffi::npp::NppiSize {
+            width: frame.width as _,
+            height: frame.height as _,
+        };
+
+        unsafe {
+            let convert = match self.format {
+                PixelFormat::Rgb24 => ffi::npp::nppiNV12ToRGB_8u_P2C3R_Ctx,
+                PixelFormat::Bgr24 => ffi::npp::nppiNV12ToBGR_8u_P2C3R_Ctx,
+            };
+            convert(
+                src.as_ptr(),
+                frame.pitch as _,
+                device.as_ptr() as *mut ffi::npp::Npp8u,
+                pitch as _,
+                roi,
+                self.stream_ctx,
+            )
+            .err()?;
+
+            ffi::cuda::cuMemcpyDtoHAsync_v2(host.ptr, device.as_ptr(), size as _, self.stream)
+                .err()?;
+            ffi::cuda::cuStreamSynchronize(self.stream).err()?;
+        }
+
+        Ok(ConvertedFrame {
+            width: frame.width,
+            height: frame.height,
+            timestamp: frame.timestamp,
+            data: host.as_slice(size).to_vec(),
+        })
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Buffers go while the context is still current.
+        self.device = None;
+        self.host = None;
+        unsafe {
+            if !self.stream.is_null() {
+                ffi::cuda::cuStreamDestroy_v2(self.stream);
+            }
+            ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+        }
+    }
+}
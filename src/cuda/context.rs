@@ -1,3 +1,7 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::CudaResult;
 use cuda::device::CuDevice;
 
 pub struct CuContext {
@@ -23,6 +27,41 @@ impl CuContext {
 
         wrap!(ver, res)
     }
+
+    /// Makes the context current on the calling thread until the guard is
+    /// dropped.
+    pub fn push(&self) -> Result<CurrentContext<'_>, ffi::cuda::CUresult> {
+        unsafe {
+            ffi::cuda::cuCtxPushCurrent_v2(self.context).err()?;
+        }
+
+        Ok(CurrentContext {
+            context: self,
+            _thread: PhantomData,
+        })
+    }
+}
+
+/// A context current on this thread, see `CuContext::push`. The functions
+/// taking one run in that context.
+pub struct CurrentContext<'a> {
+    context: &'a CuContext,
+    /// The context stack is per thread, so the guard stays on this one.
+    _thread: PhantomData<*const ()>,
+}
+
+impl CurrentContext<'_> {
+    pub fn context(&self) -> &CuContext {
+        self.context
+    }
+}
+
+impl Drop for CurrentContext<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+        }
+    }
 }
 
 impl Drop for CuContext {
@@ -35,6 +74,7 @@ impl Drop for CuContext {
 
 pub enum CuContextRef<'a> {
     Borrowed(&'a CuContext),
+    Shared(Arc<CuContext>),
     Owned(CuContext),
 }
 
@@ -44,6 +84,7 @@ impl std::ops::Deref for CuContextRef<'_> {
     fn deref(&self) -> &CuContext {
         match *self {
             CuContextRef::Borrowed(borrowed) => borrowed,
+            CuContextRef::Shared(ref shared) => shared,
             CuContextRef::Owned(ref owned) => owned,
         }
     }
@@ -0,0 +1,171 @@
+use std::os::raw::c_void;
+use std::sync::{Arc, RwLock};
+
+use super::CudaResult;
+use ffi::cuda::*;
+
+/// Source of every device allocation made by the crate.
+///
+/// Install one with `set_allocator` to route the allocations through a
+/// memory pool or to account for them together with the rest of the
+/// application. Both methods are called with the owning context current.
+pub trait Allocator: Send + Sync {
+    fn alloc(&self, size: usize) -> Result<CUdeviceptr, CUresult>;
+    fn free(&self, ptr: CUdeviceptr, size: usize);
+}
+
+/// The default allocator, plain `cuMemAlloc`/`cuMemFree`.
+pub struct CudaAllocator;
+
+impl Allocator for CudaAllocator {
+    fn alloc(&self, size: usize) -> Result<CUdeviceptr, CUresult> {
+        let mut ptr = 0;
+        let res = unsafe { cuMemAlloc_v2(&mut ptr, size.max(1) as _) };
+
+        wrap!(ptr, res)
+    }
+
+    fn free(&self, ptr: CUdeviceptr, _size: usize) {
+        unsafe {
+            cuMemFree_v2(ptr);
+        }
+    }
+}
+
+/// Stands in for the allocator of memory owned by the caller.
+struct Borrowed;
+
+impl Allocator for Borrowed {
+    fn alloc(&self, _size: usize) -> Result<CUdeviceptr, CUresult> {
+        Err(cudaError_enum_CUDA_ERROR_NOT_SUPPORTED)
+    }
+
+    fn free(&self, _ptr: CUdeviceptr, _size: usize) {}
+}
+
+static ALLOCATOR: RwLock<Option<Arc<dyn Allocator>>> = RwLock::new(None);
+
+/// Replaces the allocator used for new allocations. Existing buffers are
+/// still released through the allocator that created them.
+pub fn set_allocator(allocator: Arc<dyn Allocator>) {
+    *ALLOCATOR.write().unwrap() = Some(allocator);
+}
+
+pub fn allocator() -> Arc<dyn Allocator> {
+    match *ALLOCATOR.read().unwrap() {
+        Some(ref allocator) => Arc::clone(allocator),
+        None => Arc::new(CudaAllocator),
+    }
+}
+
+/// Linear device memory obtained from an `Allocator`.
+///
+/// The allocation belongs to the context that was current when it was
+/// created, which is made current again to free it, so the buffer can be
+/// dropped on any thread.
+pub struct DeviceBuffer {
+    pub(crate) ptr: CUdeviceptr,
+    size: usize,
+    allocator: Arc<dyn Allocator>,
+    /// Null for memory owned by the caller.
+    context: CUcontext,
+}
+
+unsafe impl Send for DeviceBuffer {}
+unsafe impl Sync for DeviceBuffer {}
+
+impl DeviceBuffer {
+    pub fn new(size: usize) -> Result<Self, CUresult> {
+        Self::with_allocator(size, allocator())
+    }
+
+    pub fn with_allocator(size: usize, allocator: Arc<dyn Allocator>) -> Result<Self, CUresult> {
+        let mut context = std::ptr::null_mut();
+        unsafe {
+            cuCtxGetCurrent(&mut context).err()?;
+        }
+        if context.is_null() {
+            return Err(cudaError_enum_CUDA_ERROR_INVALID_CONTEXT);
+        }
+        let ptr = allocator.alloc(size)?;
+
+        Ok(DeviceBuffer {
+            ptr,
+            size,
+            allocator,
+            context,
+        })
+    }
+
+    /// Wraps `size` bytes at `ptr` allocated by the caller, which are not
+    /// freed with the buffer.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay allocated for as long as the buffer lives.
+    pub unsafe fn from_raw(ptr: CUdeviceptr, size: usize) -> Self {
+        DeviceBuffer {
+            ptr,
+            size,
+            allocator: Arc::new(Borrowed),
+            context: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn as_ptr(&self) -> CUdeviceptr {
+        self.ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Copies the beginning of the buffer into `dst`, ordered after the work
+    /// already queued on `stream` (the legacy default stream if `None`).
+    pub fn copy_to_host(
+        &self,
+        dst: &mut [u8],
+        stream: Option<&super::stream::CuStream>,
+    ) -> Result<(), CUresult> {
+        if dst.len() > self.size {
+            return Err(cudaError_enum_CUDA_ERROR_INVALID_VALUE);
+        }
+        let stream = stream.map(|s| s.stream).unwrap_or(std::ptr::null_mut());
+
+        unsafe {
+            cuMemcpyDtoHAsync_v2(
+                dst.as_mut_ptr() as *mut c_void,
+                self.ptr,
+                dst.len() as _,
+                stream,
+            )
+            .err()?;
+            cuStreamSynchronize(stream).err()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for DeviceBuffer {
+    fn drop(&mut self) {
+        if self.context.is_null() {
+            return;
+        }
+
+        unsafe {
+            if !cuCtxPushCurrent_v2(self.context).ok() {
+                tracing::error!("Failed to push the buffer's context, leaking it.");
+                return;
+            }
+            self.allocator.free(self.ptr, self.size);
+            if !cuCtxPopCurrent_v2(std::ptr::null_mut()).ok() {
+                tracing::error!("Failed to pop current context.");
+            }
+        }
+    }
+}
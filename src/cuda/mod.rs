@@ -2,4 +2,5 @@ use super::CudaResult;
 
 pub mod context;
 pub mod device;
+pub mod mem;
 pub mod stream;
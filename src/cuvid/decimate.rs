@@ -0,0 +1,144 @@
+use super::decoder::CLOCK_RATE;
+
+/// Which frames a decimating decoder hands out. The others are dropped with
+/// `DropCause::Decimated` before being mapped, releasing their surface
+/// right away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decimation {
+    /// The first frame and then every `n`-th one.
+    EveryNth(u32),
+    /// At most this many frames per second of timestamps, assuming the
+    /// default 10 MHz clock.
+    MaxFps(f64),
+    /// Only keyframes. The other pictures are not even decoded.
+    Keyframes,
+    /// Only the pictures others refer to, e.g. no B-frames in most
+    /// streams. The other pictures are not even decoded.
+    ReferencePictures,
+}
+
+/// Jumps of more timestamps than this many periods, or backwards, restart
+/// the selection at the new position.
+const RESYNC_PERIODS: i64 = 8;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Decimator {
+    decimation: Decimation,
+    /// Frames left to skip before the next one kept by `EveryNth`.
+    skip: u32,
+    next: Option<i64>,
+    last: Option<i64>,
+}
+
+impl Decimator {
+    pub(crate) fn new(decimation: Decimation) -> Self {
+        Decimator {
+            decimation,
+            skip: 0,
+            next: None,
+            last: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.skip = 0;
+        self.next = None;
+        self.last = None;
+    }
+
+    /// Whether a picture is decoded at all, from whether it is a keyframe
+    /// and whether other pictures refer to it.
+    pub(crate) fn decodes(&self, keyframe: bool, reference: bool) -> bool {
+        match self.decimation {
+            Decimation::Keyframes => keyframe,
+            Decimation::ReferencePictures => keyframe || reference,
+            Decimation::EveryNth(_) | Decimation::MaxFps(_) => true,
+        }
+    }
+
+    /// Whether the frame stamped `timestamp` is kept.
+    pub(crate) fn keep(&mut self, timestamp: i64) -> bool {
+        match self.decimation {
+            // Decided before decoding.
+            Decimation::Keyframes | Decimation::ReferencePictures => true,
+            Decimation::EveryNth(n) => {
+                if self.skip > 0 {
+                    self.skip -= 1;
+                    return false;
+                }
+                self.skip = n.saturating_sub(1);
+                true
+            }
+            Decimation::MaxFps(fps) => {
+                let period = (f64::from(CLOCK_RATE) / fps).round().max(1.0) as i64;
+                // Half an input interval of slack absorbs jitter.
+                let slack = self.last.map_or(0, |last| {
+                    timestamp.saturating_sub(last).clamp(0, period) / 2
+                });
+                self.last = Some(timestamp);
+
+                let next = match self.next {
+                    Some(next)
+                        if timestamp >= next.saturating_sub(period)
+                            && timestamp
+                                < next.saturating_add(RESYNC_PERIODS.saturating_mul(period)) =>
+                    {
+                        next
+                    }
+                    _ => timestamp,
+                };
+                if timestamp < next.saturating_sub(slack) {
+                    self.next = Some(next);
+                    return false;
+                }
+
+                // Stay on the grid of kept frames unless the input fell
+                // behind it.
+                self.next = Some(if timestamp.saturating_sub(next) < period {
+                    next.saturating_add(period)
+                } else {
+                    timestamp.saturating_add(period)
+                });
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimation() {
+        let mut every_third = Decimator::new(Decimation::EveryNth(3));
+        let kept: Vec<bool> = (0..7).map(|i| every_third.keep(i)).collect();
+        assert_eq!(kept, [true, false, false, true, false, false, true]);
+
+        // 30 fps down to 5 fps keeps every sixth frame, also with jitter.
+        let mut five_fps = Decimator::new(Decimation::MaxFps(5.0));
+        let kept: Vec<i64> = (0..30)
+            .map(|i| i * 333_333 + if i % 2 == 0 { 1000 } else { -1000 })
+            .filter(|&ts| five_fps.keep(ts))
+            .collect();
+        assert_eq!(kept.len(), 5);
+
+        // Restarts after a jump back.
+        assert!(five_fps.keep(0));
+        assert!(!five_fps.keep(333_333));
+
+        // Periods too long for the timestamps saturate instead of
+        // overflowing.
+        let mut slow = Decimator::new(Decimation::MaxFps(1e-30));
+        assert!(slow.keep(0));
+        assert!(!slow.keep(1));
+        assert!(slow.keep(-1));
+
+        let references = Decimator::new(Decimation::ReferencePictures);
+        assert!(references.decodes(true, false));
+        assert!(references.decodes(false, true));
+        assert!(!references.decodes(false, false));
+        assert!(!Decimator::new(Decimation::Keyframes).decodes(false, true));
+        assert!(five_fps.decodes(false, false));
+    }
+}
@@ -0,0 +1,2676 @@
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{
+    extradata, ffi, layer, picture, sei, CUdeviceptr, Codec, DeadFrameDetector,
+    DeadFrameThresholds, Decimation, Decimator, DecodeError, DecoderEvents, DeinterlaceMode,
+    DeviceFrame, Field, FrameAllocator, FrameIntervals, FrameRecvError, FrameSkip, OperatingPoint,
+    Orientation, PacketFlags, PictureType, Rect, ReorderBuffer, SeiMessage, TimestampSmoother,
+    UnsupportedStream, VideoChromaFormat, VideoFormat, VideoSignalInfo, VideoSurfaceFormat,
+};
+#[cfg(feature = "async")]
+use super::{FrameStream, NextFrame};
+use cuda::context::{CuContext, CuContextRef, CurrentContext};
+use cuda::device::CuDevice;
+use fanout::DropPolicy;
+use CudaResult;
+
+/// A warning about a single frame, silenced with `set_frame_warnings` or
+/// `DecoderBuilder::frame_warnings`.
+macro_rules! frame_warn {
+    ($inner:expr, $($arg:tt)+) => {
+        if $inner.frame_warnings && ::frame_warnings() {
+            tracing::warn!(decoder = %$inner.name, $($arg)+);
+        }
+    };
+}
+
+pub struct Decoder {
+    inner: Box<Inner>,
+}
+
+unsafe impl Send for Decoder {}
+unsafe impl Sync for Decoder {}
+
+struct Inner {
+    parser: ffi::cuvid::CUvideoparser,
+    lock: ffi::cuvid::CUvideoctxlock,
+    context: CuContextRef<'static>,
+    decoder: ffi::cuvid::CUvideodecoder,
+    /// The decoder is created for intra pictures only.
+    keyframe_only: bool,
+    /// Pictures other than keyframes are skipped.
+    skip_to_keyframes: bool,
+    /// A change of `skip_to_keyframes` waiting for the next keyframe.
+    pending_keyframe_only: Mutex<Option<bool>>,
+    requested_size: (u32, u32),
+    /// An output size to switch to, see `Decoder::set_output_size`.
+    output_size: Mutex<Option<(u32, u32)>>,
+    max_size: (u32, u32),
+    /// The largest coded size the current decoder was created for.
+    decoder_max_size: (u32, u32),
+    surfaces: Arc<Surfaces>,
+
+    video_fmt: Option<ffi::cuvid::CUVIDEOFORMAT>,
+    codec: Codec,
+    chroma_format: VideoChromaFormat,
+    bit_depth_minus8: u8,
+    bpp: u8,
+    output_format: VideoSurfaceFormat,
+    requested_output_format: Option<VideoSurfaceFormat>,
+    out_size: (u32, u32),
+    coded_size: (u32, u32),
+    sender: Option<flume::Sender<PreparedFrame>>,
+    receiver: flume::Receiver<PreparedFrame>,
+    /// The parser signalled the end of stream, set before the sender goes.
+    eos: AtomicBool,
+    /// Why the current sequence cannot be decoded, set before the sender
+    /// goes.
+    sequence_error: Mutex<Option<DecodeError>>,
+    paused: AtomicBool,
+    requested_output_surfaces: Option<usize>,
+    requested_decode_surfaces: Option<usize>,
+    decode_surfaces: u64,
+    frame_timeout: Option<Duration>,
+    drops: [AtomicU64; DROP_CAUSES],
+    on_drop: Option<DropCallback>,
+    smoother: Option<Mutex<TimestampSmoother>>,
+    intervals: Mutex<FrameIntervals>,
+    pending_sei: Vec<Vec<SeiMessage>>,
+    picture_types: Vec<PictureType>,
+    frame_skip: Mutex<FrameSkip>,
+    /// Why the last picture of each surface was skipped rather than
+    /// decoded, if it was.
+    skipped: Vec<Option<DropCause>>,
+    on_format_change: Option<FormatCallback>,
+    events: Option<Arc<dyn DecoderEvents>>,
+    frame_allocator: Option<SharedFrameAllocator>,
+    on_frame: Option<FrameCallback>,
+    requested_deinterlace: Option<DeinterlaceMode>,
+    separate_fields: bool,
+    reorder: Option<ReorderBuffer<PreparedFrame>>,
+    operating_point: (usize, bool),
+    operating_points: Mutex<Vec<OperatingPoint>>,
+    deinterlace_mode: DeinterlaceMode,
+    detector: Option<Mutex<DeadFrameDetector>>,
+    decimator: Option<Mutex<Decimator>>,
+    overflow: DropPolicy,
+    error_policy: ErrorPolicy,
+    /// The container's orientation, replaced by display orientation SEI.
+    orientation: Orientation,
+    name: String,
+    /// Entered in the parser callbacks and while mapping frames.
+    span: tracing::Span,
+    frame_warnings: bool,
+    /// Every packet holds one whole picture.
+    end_of_picture: bool,
+    /// Size of the NAL unit length prefixes of packets to rewrite into
+    /// Annex B, 0 if they need none.
+    nal_length_size: AtomicUsize,
+    annex_b: Mutex<Vec<u8>>,
+    /// The packet assembled by `queue_vectored`.
+    gathered: Mutex<Vec<u8>>,
+    parser_params: ffi::cuvid::CUVIDPARSERPARAMS,
+    picture_buffer: Option<usize>,
+}
+
+#[derive(Debug)]
+pub(crate) struct PreparedFrame {
+    timestamp: i64,
+    index: i32,
+    parameters: ffi::cuvid::CUVIDPROCPARAMS,
+    sei: Vec<SeiMessage>,
+    picture_type: PictureType,
+    orientation: Orientation,
+    duration: Option<i64>,
+    field: Option<Field>,
+    /// The field is cut out of the woven frame rather than interpolated.
+    split: bool,
+    /// Whether the surface is released with this frame, false for the first
+    /// of two bobbed fields.
+    release: bool,
+}
+
+impl PreparedFrame {
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+pub struct GpuFrame {
+    pub width: u32,
+    pub height: u32,
+    pub ptr: CUdeviceptr,
+    pub pitch: u32,
+    pub timestamp: i64,
+    /// Time since the previous frame, the frame duration of variable frame
+    /// rate sources.
+    pub interval: Option<i64>,
+    /// How long the frame is shown at the nominal frame rate, including
+    /// repeated fields, if the stream signals a frame rate.
+    pub duration: Option<i64>,
+    /// Nominal frame rate of the stream as (numerator, denominator), if it
+    /// signals one. Doubled when fields are output as frames of their own.
+    pub frame_rate: Option<(u32, u32)>,
+    pub format: VideoSurfaceFormat,
+    /// Bit depth of the decoded content, which can be lower than the
+    /// sample size of `format`.
+    pub bit_depth: u8,
+    pub video_signal: VideoSignalInfo,
+    /// Size of the pictures in the bitstream.
+    pub coded_size: (u32, u32),
+    /// The part of the coded picture meant to be shown, which the decoder
+    /// already cropped (and scaled) the frame to.
+    pub display_area: Rect,
+    /// Width to height ratio of the samples of this frame, accounting for
+    /// the scaling to `DecoderBuilder::output_size`.
+    pub sample_aspect_ratio: (u32, u32),
+    /// How the picture has to be turned for display, which the decoder
+    /// does not do. See `filter::Chain::upright`.
+    pub orientation: Orientation,
+    /// The field shown, when fields are output on their own with
+    /// `DeinterlaceMode::Bob` or `DecoderBuilder::separate_fields`.
+    pub field: Option<Field>,
+    sei: Vec<SeiMessage>,
+    picture_type: PictureType,
+    decode_status: DecodeStatus,
+    black: bool,
+    frozen: bool,
+    release: bool,
+    surfaces: Arc<Surfaces>,
+    /// The mapping, which `ptr` points into.
+    mapped: CUdeviceptr,
+    /// The context the picture is mapped in, only current while mapping
+    /// and unmapping.
+    context: ffi::cuda::CUcontext,
+    idx: i32,
+    decoder: ffi::cuvid::CUvideodecoder,
+}
+
+// Unmapping pushes the context of its own, on whatever thread the frame is
+// dropped.
+unsafe impl Send for GpuFrame {}
+
+impl GpuFrame {
+    /// Size in bytes of the mapped picture, all planes included.
+    pub fn size(&self) -> usize {
+        self.format.frame_size(self.pitch, self.height)
+    }
+
+    /// Start of the chroma planes, which follow the luma plane with the
+    /// same pitch: interleaved for NV12, P016, NV16 and P216, U then V for
+    /// the 4:4:4 formats.
+    pub fn chroma_ptr(&self) -> CUdeviceptr {
+        self.ptr + (self.pitch * self.height) as u64
+    }
+
+    /// SEI messages of the picture, empty unless the decoder was built with
+    /// `capture_sei`.
+    pub fn sei(&self) -> &[SeiMessage] {
+        &self.sei
+    }
+
+    /// Coding type of the picture the frame was decoded from.
+    pub fn picture_type(&self) -> PictureType {
+        self.picture_type
+    }
+
+    /// Errors in the picture, only ever reported with
+    /// `ErrorPolicy::EmitWithFlag` or `DropConcealed`.
+    pub fn decode_status(&self) -> DecodeStatus {
+        self.decode_status
+    }
+
+    /// Whether the picture is (nearly) black, only ever set with
+    /// `DecoderBuilder::detect_dead_frames`.
+    pub fn is_black(&self) -> bool {
+        self.black
+    }
+
+    /// Whether the picture (nearly) repeats the previous frame, only ever
+    /// set with `DecoderBuilder::detect_dead_frames`.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Whether `current` is the context the frame is mapped in.
+    pub(crate) fn is_current(&self, current: &CurrentContext) -> bool {
+        current.context().context == self.context
+    }
+}
+
+impl Drop for GpuFrame {
+    fn drop(&mut self) {
+        // Held while unmapping so the decoder can't be destroyed meanwhile.
+        let closed = self.surfaces.closed.lock().unwrap();
+
+        unsafe {
+            if !*closed {
+                if !ffi::cuda::cuCtxPushCurrent_v2(self.context).ok() {
+                    tracing::error!("Failed to push the frame's context.");
+                } else {
+                    if !ffi::cuvid::cuvidUnmapVideoFrame64(self.decoder, self.mapped).ok() {
+                        tracing::error!("Failed to unmap current frame.");
+                    }
+                    if !ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut()).ok() {
+                        tracing::error!("Failed to pop current context.");
+                    }
+                }
+            }
+
+            if self.release {
+                self.surfaces.set(self.idx as usize, false);
+            }
+        }
+    }
+}
+
+/// Decode surface state shared by the decoder and the frames mapped from it.
+#[derive(Default)]
+struct Surfaces {
+    /// One bit per surface, set from display until the frame is dropped.
+    in_use: [AtomicU64; MAX_DECODE_SURFACES / 64],
+    /// Set once the decoder is destroyed, the frames outliving it must not
+    /// unmap anymore.
+    closed: Mutex<bool>,
+}
+
+/// Picture indexes are reported by the parser, which sizes the pool from
+/// the 8 bit minimum surface count of the sequence.
+const MAX_DECODE_SURFACES: usize = 256;
+
+impl Surfaces {
+    fn is_set(&self, idx: usize) -> bool {
+        let word = self.in_use[idx / 64].load(std::sync::atomic::Ordering::SeqCst);
+        word & (1 << (idx % 64)) != 0
+    }
+
+    fn set(&self, idx: usize, in_use: bool) {
+        let bit = 1 << (idx % 64);
+        if in_use {
+            self.in_use[idx / 64].fetch_or(bit, std::sync::atomic::Ordering::SeqCst);
+        } else {
+            self.in_use[idx / 64].fetch_and(!bit, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.in_use
+            .iter()
+            .map(|word| word.load(std::sync::atomic::Ordering::SeqCst).count_ones() as usize)
+            .sum()
+    }
+
+    fn any(&self) -> bool {
+        self.in_use
+            .iter()
+            .any(|word| word.load(std::sync::atomic::Ordering::SeqCst) != 0)
+    }
+
+    fn clear(&self) {
+        for word in &self.in_use {
+            word.store(0, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+/// How the surfaces of a decoder are used, for tuning
+/// `DecoderBuilder::decode_surfaces` and `output_surfaces`. The decoder
+/// stalls once every decode surface is in flight.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SurfaceUsage {
+    /// The decode surfaces the stream needs at the least, 0 before the
+    /// first sequence header.
+    pub min_decode_surfaces: usize,
+    /// The decode surfaces allocated, 0 before the first sequence header.
+    pub decode_surfaces: usize,
+    /// How many frames can be mapped at the same time.
+    pub output_surfaces: usize,
+    /// Surfaces holding a frame that was output but not dropped yet.
+    pub in_flight: usize,
+    /// Of those, the frames still waiting to be received.
+    pub queued: usize,
+}
+
+/// How long `decode_images` waits for an image that failed to parse.
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Timestamp units per second.
+pub(crate) const CLOCK_RATE: u32 = 10_000_000;
+
+/// Display time of `fields` fields at `frame_rate` frames per second, in
+/// timestamp units.
+fn fields_duration(frame_rate: (u32, u32), fields: i32) -> Option<i64> {
+    match frame_rate {
+        (0, _) | (_, 0) => None,
+        (num, den) if fields > 0 => {
+            Some(CLOCK_RATE as i64 * den as i64 * fields as i64 / (2 * num as i64))
+        }
+        _ => None,
+    }
+}
+
+/// How long dropping a decoder waits for its frames to be released.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// NVDEC decoders currently created by this process.
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Holds the decoder's context lock, released on drop.
+pub struct CtxLockGuard<'a> {
+    decoder: &'a Decoder,
+}
+
+impl Drop for CtxLockGuard<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::cuvid::cuvidCtxUnlock(self.decoder.inner.lock, 0);
+        }
+    }
+}
+
+/// Why a decoded picture never made it to the consumer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DropCause {
+    /// NVDEC reported a decoding error or concealed one.
+    Corrupted,
+    /// The picture could not be mapped into device memory.
+    MapFailed,
+    /// The picture was still waiting to be received on `Decoder::flush`.
+    Flushed,
+    /// Left out by `DecoderBuilder::decimate`.
+    Decimated,
+    /// The picture buffer was full, see `DecoderBuilder::overflow`.
+    Overflow,
+    /// Never decoded, see `Decoder::set_frame_skip`.
+    Skipped,
+    /// Output while the decoder was paused, see `Decoder::pause`.
+    Paused,
+    /// Stamped before a frame already output, see
+    /// `DecoderBuilder::pts_order`.
+    OutOfOrder,
+}
+
+const DROP_CAUSES: usize = 8;
+
+/// How NVDEC reported the decoding of a picture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeStatus {
+    Success,
+    /// Errors were found and concealed.
+    Concealed,
+    /// Errors were found and left as they are.
+    Error,
+}
+
+/// Which pictures with decoding errors still make it to the consumer. The
+/// others are dropped as `DropCause::Corrupted`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// Every picture, see `GpuFrame::decode_status`.
+    EmitWithFlag,
+    /// Drop the pictures NVDEC concealed errors in, which look plausible
+    /// but are wrong. Those with errors left as they are still come out,
+    /// flagged in `GpuFrame::decode_status`, for the consumer to tell apart.
+    DropConcealed,
+    /// Drop every picture with errors, the default.
+    DropAnyError,
+}
+
+impl ErrorPolicy {
+    fn drops(self, status: DecodeStatus) -> bool {
+        match (self, status) {
+            (_, DecodeStatus::Success) | (ErrorPolicy::EmitWithFlag, _) => false,
+            (ErrorPolicy::DropConcealed, status) => status == DecodeStatus::Concealed,
+            (ErrorPolicy::DropAnyError, _) => true,
+        }
+    }
+}
+
+pub type DropCallback = Arc<dyn Fn(DropCause, i64) + Send + Sync>;
+
+pub type FormatCallback = Arc<dyn Fn(&VideoFormat) + Send + Sync>;
+
+type SharedFrameAllocator = Arc<Mutex<dyn FrameAllocator>>;
+
+type FrameCallback = Box<dyn FnMut(GpuFrame) -> FrameFlow + Send>;
+
+/// What a `Decoder::on_frame` callback asks for after a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameFlow {
+    /// Keep calling it with the following frames.
+    Continue,
+    /// Stop calling it. The following frames queue up for `frames`
+    /// instead, so the consumer pulls them at its own pace and the picture
+    /// buffer, once full, applies back-pressure per the overflow policy.
+    Pull,
+}
+
+/// Named configuration for a `Decoder`.
+///
+/// ```no_run
+/// # use nvidia_video_codec::cuvid::{Codec, Decoder};
+/// let decoder = Decoder::builder(Codec::H264)
+///     .gpu(0)
+///     .output_size(1280, 720)
+///     .low_latency(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct DecoderBuilder {
+    codec: Codec,
+    gpu_id: usize,
+    context: Option<Arc<CuContext>>,
+    keyframe_only: bool,
+    low_latency: bool,
+    output_size: (u32, u32),
+    max_size: (u32, u32),
+    decode_surfaces: Option<usize>,
+    output_surfaces: Option<usize>,
+    frame_timeout: Option<Duration>,
+    picture_buffer: Option<usize>,
+    output_format: Option<VideoSurfaceFormat>,
+    blocking_sync: bool,
+    on_drop: Option<DropCallback>,
+    smoother: Option<TimestampSmoother>,
+    capture_sei: bool,
+    on_format_change: Option<FormatCallback>,
+    events: Option<Arc<dyn DecoderEvents>>,
+    frame_allocator: Option<SharedFrameAllocator>,
+    deinterlace: Option<DeinterlaceMode>,
+    separate_fields: bool,
+    pts_order: bool,
+    operating_point: (usize, bool),
+    dead_frames: Option<DeadFrameThresholds>,
+    orientation: Orientation,
+    name: String,
+    frame_warnings: bool,
+    zero_latency: bool,
+    extradata: Option<Vec<u8>>,
+    decimation: Option<Decimation>,
+    overflow: DropPolicy,
+    error_threshold: u32,
+    error_policy: ErrorPolicy,
+}
+
+impl DecoderBuilder {
+    pub fn new(codec: Codec) -> Self {
+        DecoderBuilder {
+            codec,
+            gpu_id: 0,
+            context: None,
+            keyframe_only: false,
+            low_latency: false,
+            output_size: (0, 0),
+            max_size: (0, 0),
+            decode_surfaces: None,
+            output_surfaces: None,
+            frame_timeout: None,
+            picture_buffer: None,
+            output_format: Some(VideoSurfaceFormat::NV12),
+            blocking_sync: false,
+            on_drop: None,
+            smoother: None,
+            capture_sei: false,
+            on_format_change: None,
+            events: None,
+            frame_allocator: None,
+            deinterlace: None,
+            separate_fields: false,
+            pts_order: false,
+            operating_point: (0, false),
+            dead_frames: None,
+            orientation: Orientation::default(),
+            name: String::new(),
+            frame_warnings: true,
+            zero_latency: false,
+            extradata: None,
+            decimation: None,
+            overflow: DropPolicy::Block,
+            error_threshold: 100,
+            error_policy: ErrorPolicy::DropAnyError,
+        }
+    }
+
+    /// Device used to create an owned context, ignored with `context`.
+    pub fn gpu(mut self, gpu_id: usize) -> Self {
+        self.gpu_id = gpu_id;
+        self
+    }
+
+    /// Create the owned context with `CU_CTX_SCHED_BLOCKING_SYNC`, so that
+    /// synchronizing with the GPU puts the thread to sleep instead of
+    /// spinning. Worth it with many sessions per process, at the price of a
+    /// little latency.
+    pub fn blocking_sync(mut self, blocking_sync: bool) -> Self {
+        self.blocking_sync = blocking_sync;
+        self
+    }
+
+    /// Called with the cause and timestamp of every dropped picture, on the
+    /// thread receiving the frames.
+    pub fn on_drop<F>(mut self, on_drop: F) -> Self
+    where
+        F: Fn(DropCause, i64) + Send + Sync + 'static,
+    {
+        self.on_drop = Some(Arc::new(on_drop));
+        self
+    }
+
+    /// Rewrite the frame timestamps with `smoother` to remove jitter and
+    /// clock drift, e.g. `TimestampSmoother::new()`.
+    pub fn smooth_timestamps(mut self, smoother: TimestampSmoother) -> Self {
+        self.smoother = Some(smoother);
+        self
+    }
+
+    /// Called with the new format whenever a sequence header changes it,
+    /// including the first one, on the thread queueing the packets. Frames
+    /// of the new format follow.
+    pub fn on_format_change<F>(mut self, on_format_change: F) -> Self
+    where
+        F: Fn(&VideoFormat) + Send + Sync + 'static,
+    {
+        self.on_format_change = Some(Arc::new(on_format_change));
+        self
+    }
+
+    /// Reports sequence changes, decode errors, surface starvation and the
+    /// end of stream to `events`.
+    pub fn events<E: DecoderEvents + 'static>(mut self, events: E) -> Self {
+        self.events = Some(Arc::new(events));
+        self
+    }
+
+    /// Where `FramesIter::try_next_copied` gets the frames it copies into,
+    /// e.g. a ring of buffers preallocated by the caller.
+    pub fn frame_allocator<A>(mut self, allocator: A) -> Self
+    where
+        A: FrameAllocator + 'static,
+    {
+        self.frame_allocator = Some(Arc::new(Mutex::new(allocator)));
+        self
+    }
+
+    /// Deinterlacing of interlaced sequences, `Adaptive` by default.
+    /// Progressive sequences are always woven. With `Bob` both fields are
+    /// output as frames of their own, the second one stamped half a frame
+    /// later, assuming the default 10 MHz timestamps.
+    pub fn deinterlace(mut self, mode: DeinterlaceMode) -> Self {
+        self.deinterlace = Some(mode);
+        self
+    }
+
+    /// Output both fields of interlaced pictures as frames of their own,
+    /// as they are coded rather than interpolated like with `Bob`: half
+    /// the height, with twice the pitch. They come in display order, the
+    /// second one stamped half a frame later. Replaces the deinterlacing.
+    pub fn separate_fields(mut self, separate_fields: bool) -> Self {
+        self.separate_fields = separate_fields;
+        self
+    }
+
+    /// The operating point of a scalable AV1 stream to decode, out of
+    /// `Decoder::operating_points`, and whether every layer it contains is
+    /// output rather than only the highest one. The first operating point,
+    /// usually all layers, by default.
+    pub fn operating_point(mut self, index: usize, all_layers: bool) -> Self {
+        self.operating_point = (index, all_layers);
+        self
+    }
+
+    /// Output frames in ascending timestamp order, also when the stream's
+    /// timestamps disagree with its display order. A few frames more than
+    /// the display delay are held back to sort them, and frames stamped
+    /// before one already output are dropped as `DropCause::OutOfOrder`.
+    pub fn pts_order(mut self, pts_order: bool) -> Self {
+        self.pts_order = pts_order;
+        self
+    }
+
+    /// Measure every 8 bit NV12 frame for `GpuFrame::is_black` and
+    /// `GpuFrame::is_frozen`. This costs two reductions and a synchronous
+    /// read back per frame.
+    pub fn detect_dead_frames(mut self, thresholds: DeadFrameThresholds) -> Self {
+        self.dead_frames = Some(thresholds);
+        self
+    }
+
+    /// Display orientation of the stream known from the container, set on
+    /// every frame until a display orientation SEI overrides it, which is
+    /// only looked at with `capture_sei`.
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Hand out only some of the frames, e.g. for analytics needing a few
+    /// frames per second or thumbnails. The others are never mapped, and
+    /// with `Decimation::Keyframes` or `Decimation::ReferencePictures` not
+    /// decoded either.
+    pub fn decimate(mut self, decimation: Decimation) -> Self {
+        self.decimation = Some(decimation);
+        self
+    }
+
+    /// Keep the SEI messages of every picture, see `GpuFrame::sei`.
+    pub fn capture_sei(mut self, capture_sei: bool) -> Self {
+        self.capture_sei = capture_sei;
+        self
+    }
+
+    /// Codec headers from the container, see `Decoder::set_extradata`.
+    pub fn extradata(mut self, extradata: Vec<u8>) -> Self {
+        self.extradata = Some(extradata);
+        self
+    }
+
+    /// Name recorded as the `decoder` field of every event the decoder
+    /// logs and as the `name` field of its span, to tell sessions apart.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Log warnings about single frames, e.g. corrupted pictures on lossy
+    /// links. Errors are always logged.
+    pub fn frame_warnings(mut self, frame_warnings: bool) -> Self {
+        self.frame_warnings = frame_warnings;
+        self
+    }
+
+    /// Decode in an existing context instead of creating one. The decoder
+    /// keeps it alive.
+    pub fn context(mut self, context: Arc<CuContext>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    pub(crate) fn unset_context(mut self) -> Self {
+        self.context = None;
+        self
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Scale the decoded pictures to `width`x`height`, both zero keeps the
+    /// display size of the stream.
+    pub fn output_size(mut self, width: u32, height: u32) -> Self {
+        self.output_size = (width, height);
+        self
+    }
+
+    /// Size the decoder for coded pictures of up to `width`x`height` from
+    /// the start, so that resolution changes within it, as in adaptive
+    /// bitrate streams, reconfigure the decoder instead of recreating it.
+    pub fn max_size(mut self, width: u32, height: u32) -> Self {
+        self.max_size = (width, height);
+        self
+    }
+
+    pub fn decode_surfaces(mut self, surfaces: usize) -> Self {
+        self.decode_surfaces = Some(surfaces);
+        self
+    }
+
+    pub fn output_surfaces(mut self, surfaces: usize) -> Self {
+        self.output_surfaces = Some(surfaces);
+        self
+    }
+
+    /// How long `FramesIter` waits for the next frame.
+    pub fn frame_timeout(mut self, timeout: Duration) -> Self {
+        self.frame_timeout = Some(timeout);
+        self
+    }
+
+    /// Bound the number of decoded pictures waiting to be mapped.
+    pub fn picture_buffer(mut self, pictures: usize) -> Self {
+        self.picture_buffer = Some(pictures);
+        self
+    }
+
+    /// Percentage of corrupted macroblocks above which the parser does not
+    /// even display a picture, 100 by default.
+    pub fn error_threshold(mut self, percent: u32) -> Self {
+        self.error_threshold = percent;
+        self
+    }
+
+    /// Which pictures with decoding errors are handed out anyway.
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// What happens to a decoded picture when the `picture_buffer` is full,
+    /// `Block` by default: the parser then waits for the consumer. Live
+    /// viewers rather drop frames to keep the latency bounded. Dropped
+    /// pictures are counted as `DropCause::Overflow`.
+    pub fn overflow(mut self, policy: DropPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    /// Hand pictures out without the parser's display delay.
+    pub fn low_latency(mut self, low_latency: bool) -> Self {
+        self.low_latency = low_latency;
+        self
+    }
+
+    /// `low_latency`, and every queued packet ends its picture, so that the
+    /// frame is output as soon as it is decoded instead of when the next
+    /// packet starts. Packets must then hold exactly one whole picture
+    /// (access unit) each, as with live streams sent without B-frames.
+    pub fn zero_latency(mut self, zero_latency: bool) -> Self {
+        self.zero_latency = zero_latency;
+        self
+    }
+
+    /// Decode keyframes only, skipping the other pictures, with a decoder
+    /// sized for intra pictures. `Decoder::set_keyframe_only` switches a
+    /// regular decoder at runtime instead.
+    pub fn keyframe_only(mut self, keyframe_only: bool) -> Self {
+        self.keyframe_only = keyframe_only;
+        self
+    }
+
+    /// Surface format of the mapped frames, NV12 by default. When the GPU
+    /// cannot produce it for a stream the first supported format out of
+    /// NV12, P016, YUV444, YUV444_16, NV16 and P216 is used instead, check
+    /// `GpuFrame::format`.
+    pub fn output_format(mut self, format: VideoSurfaceFormat) -> Self {
+        self.output_format = Some(format);
+        self
+    }
+
+    /// Pick the output format matching each stream's chroma format and bit
+    /// depth, e.g. P016 for 10 bit 4:2:0 content.
+    pub fn native_output_format(mut self) -> Self {
+        self.output_format = None;
+        self
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        match self.codec {
+            Codec::YUV420 | Codec::YV12 | Codec::NV12 | Codec::YUYV | Codec::UYVY => {
+                return Err("uncompressed formats cannot be parsed");
+            }
+            _ => {}
+        }
+        if (self.output_size.0 == 0) != (self.output_size.1 == 0) {
+            return Err("output size needs both width and height");
+        }
+        if (self.max_size.0 == 0) != (self.max_size.1 == 0) {
+            return Err("max size needs both width and height");
+        }
+        if let Some(surfaces) = self.decode_surfaces {
+            if surfaces == 0 || surfaces > MAX_DECODE_SURFACES {
+                return Err("decode surfaces must be between 1 and 256");
+            }
+        }
+        if self.output_surfaces == Some(0) {
+            return Err("output surfaces must be at least 1");
+        }
+        if self.picture_buffer == Some(0) {
+            return Err("picture buffer must hold at least 1 picture");
+        }
+        if self.error_threshold > 100 {
+            return Err("error threshold is a percentage");
+        }
+        if let Some(Decimation::MaxFps(fps)) = self.decimation {
+            if !fps.is_finite() || fps <= 0.0 {
+                return Err("decimation frame rate must be finite and positive");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<Decoder, DecodeError> {
+        self.validate().map_err(DecodeError::InvalidConfig)?;
+
+        let context = match self.context {
+            Some(context) => CuContextRef::Shared(context),
+            None => {
+                let device = CuDevice::new(self.gpu_id as _)?;
+                let flags = if self.blocking_sync {
+                    ffi::cuda::CUctx_flags_enum_CU_CTX_SCHED_BLOCKING_SYNC
+                } else {
+                    0
+                };
+                let context = CuContext::new(device, flags as _)?;
+                CuContextRef::Owned(context)
+            }
+        };
+
+        let mut parser: ffi::cuvid::CUvideoparser = std::ptr::null_mut();
+        let mut ctx_lock: ffi::cuvid::CUvideoctxlock = std::ptr::null_mut();
+
+        unsafe {
+            let res = ffi::cuvid::cuvidCtxLockCreate(&mut ctx_lock, context.context as _);
+            wrap!(res, res)?;
+        }
+        let (sender, receiver) = match self.picture_buffer {
+            Some(buf) => flume::bounded(buf),
+            None => flume::unbounded(),
+        };
+        // JPEG pictures are never reordered, holding one back only adds
+        // latency.
+        let display_delay = if self.low_latency || self.zero_latency || self.codec == Codec::JPEG {
+            0
+        } else {
+            1
+        };
+
+        let span = tracing::info_span!(
+            "decoder",
+            name = %self.name,
+            gpu = tracing::field::Empty,
+            codec = ?self.codec,
+        );
+        if let CuContextRef::Owned(_) = context {
+            span.record("gpu", self.gpu_id as u64);
+        }
+
+        let mut inner = Box::new(Inner {
+            parser,
+            context,
+            codec: self.codec,
+            lock: ctx_lock,
+            chroma_format: VideoChromaFormat::Monochrome,
+            decoder: std::ptr::null_mut(),
+            surfaces: Default::default(),
+            keyframe_only: self.keyframe_only,
+            skip_to_keyframes: self.keyframe_only,
+            pending_keyframe_only: Mutex::new(None),
+            video_fmt: None,
+            bit_depth_minus8: 0,
+            bpp: 0,
+            output_format: VideoSurfaceFormat::NV12,
+            requested_output_format: self.output_format,
+            out_size: (0, 0),
+            coded_size: (0, 0),
+            requested_size: self.output_size,
+            output_size: Mutex::new(None),
+            max_size: self.max_size,
+            decoder_max_size: (0, 0),
+            receiver,
+            eos: AtomicBool::new(false),
+            sequence_error: Mutex::new(None),
+            paused: AtomicBool::new(false),
+            requested_output_surfaces: self.output_surfaces,
+            requested_decode_surfaces: self.decode_surfaces,
+            decode_surfaces: 0,
+            sender: Some(sender),
+            frame_timeout: self.frame_timeout,
+            drops: Default::default(),
+            on_drop: self.on_drop,
+            smoother: self.smoother.map(Mutex::new),
+            intervals: Default::default(),
+            pending_sei: Vec::new(),
+            picture_types: Vec::new(),
+            frame_skip: Mutex::new(FrameSkip::Never),
+            skipped: Vec::new(),
+            on_format_change: self.on_format_change,
+            events: self.events,
+            frame_allocator: self.frame_allocator,
+            on_frame: None,
+            requested_deinterlace: self.deinterlace,
+            separate_fields: self.separate_fields,
+            reorder: if self.pts_order {
+                Some(ReorderBuffer::new(display_delay as usize + 1))
+            } else {
+                None
+            },
+            operating_point: self.operating_point,
+            operating_points: Mutex::new(Vec::new()),
+            deinterlace_mode: DeinterlaceMode::Weave,
+            detector: self
+                .dead_frames
+                .map(|thresholds| Mutex::new(DeadFrameDetector::new(thresholds))),
+            decimator: self
+                .decimation
+                .map(|decimation| Mutex::new(Decimator::new(decimation))),
+            overflow: self.overflow,
+            error_policy: self.error_policy,
+            orientation: self.orientation,
+            name: self.name,
+            span,
+            frame_warnings: self.frame_warnings,
+            end_of_picture: self.zero_latency,
+            nal_length_size: AtomicUsize::new(0),
+            annex_b: Mutex::new(Vec::new()),
+            gathered: Mutex::new(Vec::new()),
+            parser_params: unsafe { std::mem::zeroed() },
+            picture_buffer: self.picture_buffer,
+        });
+
+        let mut params: ffi::cuvid::CUVIDPARSERPARAMS = unsafe { std::mem::zeroed() };
+        params.CodecType = self.codec.into();
+        params.ulMaxNumDecodeSurfaces = self.decode_surfaces.unwrap_or(1) as _;
+        params.ulClockRate = CLOCK_RATE;
+        params.ulErrorThreshold = self.error_threshold;
+        params.ulMaxDisplayDelay = display_delay;
+        params.pfnSequenceCallback = Some(handle_video_sequence_proc);
+        params.pfnDecodePicture = Some(handle_picture_decode_proc);
+        params.pfnDisplayPicture = Some(handle_picture_display_proc);
+        params.pfnGetOperatingPoint = Some(handle_operating_point_proc);
+        if self.capture_sei {
+            params.pfnGetSEIMsg = Some(handle_sei_msg_proc);
+        }
+        params.pUserData = (&mut *inner as *mut Inner) as *mut std::os::raw::c_void;
+        inner.parser_params = params;
+
+        unsafe {
+            let res = ffi::cuvid::cuvidCreateVideoParser(&mut parser, &mut params);
+            wrap!(res, res).map_err(DecodeError::Parser)?;
+        }
+        inner.parser = parser;
+
+        let decoder = Decoder { inner };
+        if let Some(ref extradata) = self.extradata {
+            decoder.set_extradata(extradata)?;
+        }
+
+        Ok(decoder)
+    }
+}
+
+impl Decoder {
+    pub fn builder(codec: Codec) -> DecoderBuilder {
+        DecoderBuilder::new(codec)
+    }
+
+    pub fn create(
+        gpu_id: usize,
+        context: Option<Arc<CuContext>>,
+        codec: Codec,
+        keyframe_only: bool,
+        low_latency: bool,
+        output_size: (u32, u32),
+        decode_surfaces: Option<usize>,
+        output_surfaces: Option<usize>,
+        frame_timeout: Option<Duration>,
+        picture_buffer: Option<usize>,
+    ) -> Result<Self, DecodeError> {
+        let mut builder = DecoderBuilder::new(codec)
+            .gpu(gpu_id)
+            .keyframe_only(keyframe_only)
+            .low_latency(low_latency)
+            .output_size(output_size.0, output_size.1);
+        if let Some(context) = context {
+            builder = builder.context(context);
+        }
+        if let Some(surfaces) = decode_surfaces {
+            builder = builder.decode_surfaces(surfaces);
+        }
+        if let Some(surfaces) = output_surfaces {
+            builder = builder.output_surfaces(surfaces);
+        }
+        if let Some(timeout) = frame_timeout {
+            builder = builder.frame_timeout(timeout);
+        }
+        if let Some(pictures) = picture_buffer {
+            builder = builder.picture_buffer(pictures);
+        }
+
+        builder.build()
+    }
+
+    pub fn queue(&self, data: &[u8], timestamp: i64) -> Result<(), DecodeError> {
+        self.queue_with_flags(data, timestamp, PacketFlags::empty())
+    }
+
+    /// `queue` for a packet split over several buffers, e.g. one per NAL
+    /// unit. They are copied into a buffer kept by the decoder, so no
+    /// allocation is needed per packet once it has grown.
+    pub fn queue_vectored(&self, data: &[IoSlice<'_>], timestamp: i64) -> Result<(), DecodeError> {
+        if let [data] = data {
+            return self.queue(data, timestamp);
+        }
+
+        let mut packet = self.inner.gathered.lock().unwrap();
+        packet.clear();
+        for slice in data {
+            packet.extend_from_slice(slice);
+        }
+
+        self.queue(&packet, timestamp)
+    }
+
+    /// `queue` with packet flags, e.g. `PacketFlags::DISCONTINUITY` for the
+    /// first packet after a gap.
+    pub fn queue_with_flags(
+        &self,
+        data: &[u8],
+        timestamp: i64,
+        mut flags: PacketFlags,
+    ) -> Result<(), DecodeError> {
+        if self.inner.end_of_picture {
+            flags |= PacketFlags::END_OF_PICTURE;
+        }
+        let flags = ffi::cuvid::CUvideopacketflags_CUVID_PKT_TIMESTAMP
+            | ffi::cuvid::CUvideopacketflags::from(flags);
+
+        match self
+            .inner
+            .nal_length_size
+            .load(std::sync::atomic::Ordering::SeqCst)
+        {
+            0 => self.parse(data, flags, timestamp),
+            length_size => {
+                let mut annex_b = self.inner.annex_b.lock().unwrap();
+                extradata::to_annex_b(data, length_size, &mut annex_b)?;
+                self.parse(&annex_b, flags, timestamp)
+            }
+        }
+    }
+
+    /// Feeds the codec headers the container carries out of band, e.g. the
+    /// `avcC` or `hvcC` record of MP4 and Matroska tracks, ahead of the
+    /// packets. With such a record the packets are taken to hold length
+    /// prefixed NAL units and are rewritten into Annex B by `queue`.
+    /// Anything else, e.g. Annex B parameter sets, is passed on as is.
+    pub fn set_extradata(&self, extradata: &[u8]) -> Result<(), DecodeError> {
+        let (headers, length_size) = extradata::parse(self.inner.codec, extradata)?;
+        self.inner.nal_length_size.store(
+            length_size.unwrap_or(0),
+            std::sync::atomic::Ordering::SeqCst,
+        );
+
+        self.parse(&headers, 0, 0)
+    }
+
+    fn parse(
+        &self,
+        data: &[u8],
+        flags: ffi::cuvid::CUvideopacketflags,
+        timestamp: i64,
+    ) -> Result<(), DecodeError> {
+        self.inner.check_sequence()?;
+        let mut packet = ffi::cuvid::CUVIDSOURCEDATAPACKET {
+            flags: flags as _,
+            payload_size: data.len() as u64,
+            payload: data.as_ptr(),
+            timestamp,
+        };
+
+        unsafe {
+            let res = ffi::cuvid::cuvidParseVideoData(self.inner.parser, &mut packet);
+            wrap!(res, res).map_err(DecodeError::Parser)?;
+        }
+
+        self.inner.check_sequence()
+    }
+
+    /// Decodes a batch of JPEG images at once and returns their frames in
+    /// order, stamped with the given timestamps.
+    ///
+    /// All images are submitted before the first one is mapped, so NVDEC
+    /// works on them back to back. The frames all stay mapped, a batch can
+    /// therefore not be larger than the output surfaces (nor the picture
+    /// buffer), and the previous batch must be dropped first. Corrupted
+    /// images are left out.
+    pub fn decode_images(&self, images: &[(&[u8], i64)]) -> Result<Vec<GpuFrame>, DecodeError> {
+        if self.inner.codec != Codec::JPEG {
+            return Err(DecodeError::InvalidConfig("image batches need Codec::JPEG"));
+        }
+        let capacity = self
+            .inner
+            .requested_output_surfaces
+            .unwrap_or(3)
+            .min(self.inner.picture_buffer.unwrap_or(usize::MAX));
+        if images.len() > capacity {
+            return Err(DecodeError::InvalidConfig(
+                "more images than output surfaces or picture buffer slots",
+            ));
+        }
+
+        for &(data, timestamp) in images {
+            let mut packet = ffi::cuvid::CUVIDSOURCEDATAPACKET {
+                flags: (ffi::cuvid::CUvideopacketflags_CUVID_PKT_TIMESTAMP
+                    | ffi::cuvid::CUvideopacketflags_CUVID_PKT_ENDOFPICTURE)
+                    as _,
+                payload_size: data.len() as u64,
+                payload: data.as_ptr(),
+                timestamp,
+            };
+
+            unsafe {
+                let res = ffi::cuvid::cuvidParseVideoData(self.inner.parser, &mut packet);
+                wrap!(res, res).map_err(DecodeError::Parser)?;
+            }
+        }
+
+        let mut iter = self.frames(None);
+        iter.frame_timeout = Some(self.inner.frame_timeout.unwrap_or(IMAGE_TIMEOUT));
+        let mut frames = Vec::with_capacity(images.len());
+        for _ in 0..images.len() {
+            match iter.try_next() {
+                Ok(frame) => frames.push(frame),
+                Err(DecodeError::Corrupted { .. }) => {}
+                Err(DecodeError::Timeout) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(frames)
+    }
+
+    pub fn send_eos(&self) -> Result<(), DecodeError> {
+        let mut packet: ffi::cuvid::CUVIDSOURCEDATAPACKET = unsafe { std::mem::zeroed() };
+        packet.flags = (ffi::cuvid::CUvideopacketflags_CUVID_PKT_ENDOFSTREAM
+            | ffi::cuvid::CUvideopacketflags_CUVID_PKT_NOTIFY_EOS) as _;
+
+        unsafe {
+            let res = ffi::cuvid::cuvidParseVideoData(self.inner.parser, &mut packet);
+            wrap!(res, res).map_err(DecodeError::Parser)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends the stream, hands every frame still to come to `on_frame` and
+    /// destroys the decoder, in that order.
+    ///
+    /// Dropping a decoder while frames are still queued or mapped elsewhere
+    /// is what leads to deadlocks at teardown, this does it in a safe order:
+    /// the end of stream is signalled from a helper thread so that a bounded
+    /// picture buffer cannot block it, frames are received on the calling
+    /// thread and the context goes last with the decoder.
+    pub fn finish<F>(self, mut on_frame: F) -> Result<(), DecodeError>
+    where
+        F: FnMut(GpuFrame),
+    {
+        let decoder = &self;
+
+        std::thread::scope(|scope| {
+            let eos = scope.spawn(move || decoder.send_eos());
+            let mut frames = decoder.frames(None);
+            frames.frame_timeout = Some(Duration::from_millis(10));
+
+            loop {
+                match frames.try_next() {
+                    Ok(frame) => on_frame(frame),
+                    Err(DecodeError::Eos) => break,
+                    // A failed end of stream never closes the channel.
+                    Err(DecodeError::Timeout) if eos.is_finished() => break,
+                    // Dropped pictures are already accounted for.
+                    Err(_) => {}
+                }
+            }
+
+            eos.join().unwrap()
+        })
+    }
+
+    /// Destroys the decoder, waiting at most `timeout` for the frames still
+    /// mapped to be dropped. Returns false if some were not released in
+    /// time: their surfaces are then reclaimed with the decoder and dropping
+    /// them later does nothing, but their pointers must not be read anymore.
+    ///
+    /// Dropping the decoder does the same with a 5 seconds timeout.
+    pub fn close_timeout(mut self, timeout: Duration) -> bool {
+        self.close(timeout)
+    }
+
+    fn close(&mut self, timeout: Duration) -> bool {
+        unsafe {
+            if !self.inner.parser.is_null() {
+                ffi::cuvid::cuvidDestroyVideoParser(self.inner.parser);
+                self.inner.parser = std::ptr::null_mut();
+            }
+        }
+        while let Ok(frame) = self.inner.receiver.try_recv() {
+            self.inner.drop_frame(&frame, DropCause::Flushed);
+        }
+
+        let start = std::time::Instant::now();
+        let mut released = true;
+        while self.inner.surfaces.any() {
+            if start.elapsed() > timeout {
+                tracing::error!(
+                    decoder = %self.inner.name,
+                    "Frames still mapped {}ms after closing the decoder, reclaiming their surfaces. \
+                     Leaked GpuFrames must not be used anymore.",
+                    start.elapsed().as_millis()
+                );
+                released = false;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut closed = self.inner.surfaces.closed.lock().unwrap();
+        *closed = true;
+        unsafe {
+            if !self.inner.decoder.is_null() {
+                ffi::cuda::cuCtxPushCurrent_v2(self.inner.context.context);
+                ffi::cuvid::cuvidDestroyDecoder(self.inner.decoder);
+                SESSIONS.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                self.inner.decoder = std::ptr::null_mut();
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+            }
+            self.inner.surfaces.clear();
+            if !self.inner.lock.is_null() {
+                ffi::cuvid::cuvidCtxLockDestroy(self.inner.lock);
+                self.inner.lock = std::ptr::null_mut();
+            }
+        }
+
+        released
+    }
+
+    /// Discards everything queued so far so that packets from another
+    /// position in the stream can be fed next, e.g. after a seek.
+    ///
+    /// The parser is recreated, which drops its partially parsed data and
+    /// the pictures waiting to be displayed, and frames not yet received are
+    /// dropped with `DropCause::Flushed`. The decoder and its surfaces are
+    /// kept, frames still held by the caller stay valid. This also rearms a
+    /// decoder that already received `send_eos`.
+    pub fn flush(&mut self) -> Result<(), DecodeError> {
+        unsafe {
+            ffi::cuvid::cuvidDestroyVideoParser(self.inner.parser);
+        }
+        self.inner.parser = std::ptr::null_mut();
+
+        while let Ok(frame) = self.inner.receiver.try_recv() {
+            self.inner.drop_frame(&frame, DropCause::Flushed);
+        }
+        if self.inner.sender.is_none() {
+            let (sender, receiver) = match self.inner.picture_buffer {
+                Some(buf) => flume::bounded(buf),
+                None => flume::unbounded(),
+            };
+            self.inner.sender = Some(sender);
+            self.inner.receiver = receiver;
+            self.inner
+                .eos
+                .store(false, std::sync::atomic::Ordering::SeqCst);
+        }
+        *self.inner.sequence_error.lock().unwrap() = None;
+        if let Some(ref mut reorder) = self.inner.reorder {
+            for frame in reorder.reset() {
+                self.inner.drop_frame(&frame, DropCause::Flushed);
+            }
+        }
+        if let Some(ref smoother) = self.inner.smoother {
+            smoother.lock().unwrap().reset();
+        }
+        self.inner.intervals.lock().unwrap().restart();
+        if let Some(ref detector) = self.inner.detector {
+            detector.lock().unwrap().reset();
+        }
+        if let Some(ref decimator) = self.inner.decimator {
+            decimator.lock().unwrap().reset();
+        }
+        self.inner.pending_sei.clear();
+
+        let mut parser = std::ptr::null_mut();
+        let mut params = self.inner.parser_params;
+        unsafe {
+            let res = ffi::cuvid::cuvidCreateVideoParser(&mut parser, &mut params);
+            wrap!(res, res).map_err(DecodeError::Parser)?;
+        }
+        self.inner.parser = parser;
+
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// See `DecoderBuilder::name`.
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.inner.name = name.into();
+        self.inner
+            .span
+            .record("name", tracing::field::display(&self.inner.name));
+    }
+
+    /// The span of the decoder, carrying its name, GPU and codec, to nest
+    /// the work done on its frames under.
+    pub fn span(&self) -> &tracing::Span {
+        &self.inner.span
+    }
+
+    pub fn surface_usage(&self) -> SurfaceUsage {
+        SurfaceUsage {
+            min_decode_surfaces: self
+                .inner
+                .video_fmt
+                .as_ref()
+                .map_or(0, |fmt| fmt.min_num_decode_surfaces as usize),
+            decode_surfaces: self.inner.decode_surfaces as usize,
+            output_surfaces: self.inner.requested_output_surfaces.unwrap_or(3),
+            in_flight: self.inner.surfaces.count(),
+            queued: self.inner.receiver.len(),
+        }
+    }
+
+    /// Format of the current sequence, once the first sequence header has
+    /// been parsed.
+    pub fn video_format(&self) -> Option<VideoFormat> {
+        self.inner.video_fmt.as_ref().map(VideoFormat::from)
+    }
+
+    /// The operating points of the current AV1 sequence, see
+    /// `DecoderBuilder::operating_point`. Empty for streams of other codecs.
+    pub fn operating_points(&self) -> Vec<OperatingPoint> {
+        self.inner.operating_points.lock().unwrap().clone()
+    }
+
+    /// Colour description of the current sequence, once it is known. Look
+    /// for `SeiMessage::mastering_display` with `capture_sei` for the HDR10
+    /// static metadata.
+    pub fn video_signal_info(&self) -> Option<VideoSignalInfo> {
+        self.inner.video_fmt.as_ref().map(VideoSignalInfo::from)
+    }
+
+    /// Leave some pictures undecoded from now on, e.g. to drop B-frames
+    /// rather than fall further behind on an overloaded GPU. They are
+    /// counted as `DropCause::Skipped`.
+    pub fn set_frame_skip(&self, skip: FrameSkip) {
+        *self.inner.frame_skip.lock().unwrap() = skip;
+    }
+
+    pub fn frame_skip(&self) -> FrameSkip {
+        *self.inner.frame_skip.lock().unwrap()
+    }
+
+    /// Stops delivering frames, e.g. to freeze a live stream in a UI, and
+    /// drops those waiting to be received. Packets are still decoded, the
+    /// frames output meanwhile are dropped as `DropCause::Paused` without
+    /// holding a surface.
+    pub fn pause(&self) {
+        self.inner
+            .paused
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        while let Ok(frame) = self.inner.receiver.try_recv() {
+            self.inner.drop_frame(&frame, DropCause::Paused);
+        }
+    }
+
+    /// Delivers frames again, from the next one output.
+    pub fn resume(&self) {
+        self.inner
+            .paused
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Pushes the frames to `on_frame` as they are output, on the thread
+    /// queueing the packets, rather than through `frames`. Frames already
+    /// waiting there stay. The callback blocking holds up `queue`, or it
+    /// can return `FrameFlow::Pull` to go back to pulling.
+    pub fn on_frame<F>(&mut self, on_frame: F)
+    where
+        F: FnMut(GpuFrame) -> FrameFlow + Send + 'static,
+    {
+        self.inner.on_frame = Some(Box::new(on_frame));
+    }
+
+    /// Decode only keyframes, or everything again, from the next keyframe
+    /// on. The other pictures are counted as `DropCause::Skipped`.
+    ///
+    /// A decoder built with `DecoderBuilder::keyframe_only` is sized for
+    /// intra pictures and cannot go back to decoding everything.
+    pub fn set_keyframe_only(&self, keyframe_only: bool) -> Result<(), DecodeError> {
+        if self.inner.keyframe_only && !keyframe_only {
+            return Err(DecodeError::InvalidConfig(
+                "decoder was built for keyframes only",
+            ));
+        }
+        *self.inner.pending_keyframe_only.lock().unwrap() = Some(keyframe_only);
+
+        Ok(())
+    }
+
+    /// Scale to `width`x`height` from the next sequence, or the next
+    /// keyframe decoded while no frame is held, both zero for the display
+    /// size of the stream. The decoder is reconfigured, not recreated.
+    pub fn set_output_size(&self, width: u32, height: u32) -> Result<(), DecodeError> {
+        if (width == 0) != (height == 0) {
+            return Err(DecodeError::InvalidConfig(
+                "output size needs both width and height",
+            ));
+        }
+        *self.inner.output_size.lock().unwrap() = Some((width, height));
+
+        Ok(())
+    }
+
+    /// NVDEC sessions, i.e. decoders that parsed a sequence header, open in
+    /// this process.
+    pub fn sessions() -> usize {
+        SESSIONS.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// The context the decoder runs in, which frames are mapped in unless
+    /// `frames` is given another one.
+    pub fn context(&self) -> &CuContext {
+        &self.inner.context
+    }
+
+    /// Takes the context lock shared with NVDEC, for running kernels on
+    /// the decoder's surfaces or context without racing its own work. The
+    /// decoder's context is current while the guard is held.
+    pub fn lock_ctx(&self) -> Result<CtxLockGuard<'_>, DecodeError> {
+        unsafe {
+            ffi::cuvid::cuvidCtxLock(self.inner.lock, 0).err()?;
+        }
+
+        Ok(CtxLockGuard { decoder: self })
+    }
+
+    /// Whether the frames received so far were unevenly spaced, i.e. the
+    /// source has a variable frame rate and `GpuFrame::interval` should be
+    /// used for timing rather than the nominal frame rate.
+    pub fn is_variable_frame_rate(&self) -> bool {
+        self.inner.intervals.lock().unwrap().is_variable()
+    }
+
+    /// Number of pictures reported as corrupted by NVDEC so far.
+    pub fn decode_errors(&self) -> u64 {
+        self.drops(DropCause::Corrupted)
+    }
+
+    /// Number of decoded pictures dropped so far for `cause`.
+    pub fn drops(&self, cause: DropCause) -> u64 {
+        self.inner.drops[cause as usize].load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn frames<'a, 'b>(&'a self, context: Option<&'b CuContext>) -> FramesIter<'a, 'b> {
+        FramesIter {
+            inner: &self.inner,
+            frame_timeout: self.inner.frame_timeout,
+            context,
+        }
+    }
+
+    /// The frames as an async `Stream`, which ends with the stream. Like
+    /// `FramesIter::try_next`, it yields `Timeout` once no frame came in
+    /// the frame timeout and goes on, and `Corrupted` for dropped pictures.
+    #[cfg(feature = "async")]
+    pub fn stream<'a, 'b>(&'a self, context: Option<&'b CuContext>) -> FrameStream<'a, 'b> {
+        FrameStream::new(self.frames(context))
+    }
+
+    /// Waits for the next frame, none at the end of the stream.
+    ///
+    /// Dropping the future before it completes, e.g. in a `select!`, loses
+    /// no frame: frames are only taken off the queue, and mapped, in the
+    /// poll that returns them.
+    #[cfg(feature = "async")]
+    pub fn next_frame(&self) -> NextFrame<'_> {
+        NextFrame::new(self.frames(None))
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        self.close(CLOSE_TIMEOUT);
+    }
+}
+
+impl Inner {
+    fn video_signal(&self) -> VideoSignalInfo {
+        self.video_fmt
+            .as_ref()
+            .map(VideoSignalInfo::from)
+            .unwrap_or_default()
+    }
+
+    fn drop_frame(&self, frame: &PreparedFrame, cause: DropCause) {
+        if frame.release {
+            self.set_frame_status(frame.index as usize, false);
+        }
+        self.record_drop(cause, frame.timestamp());
+    }
+
+    fn record_drop(&self, cause: DropCause, timestamp: i64) {
+        self.drops[cause as usize].fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        if let Some(ref on_drop) = self.on_drop {
+            on_drop(cause, timestamp);
+        }
+    }
+
+    fn is_frame_in_use(&self, idx: usize) -> bool {
+        self.surfaces.is_set(idx)
+    }
+
+    fn set_frame_status(&self, idx: usize, status: bool) {
+        self.surfaces.set(idx, status);
+    }
+
+    /// The failure of the current sequence, if any.
+    fn check_sequence(&self) -> Result<(), DecodeError> {
+        match *self.sequence_error.lock().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn sequence_cb(&mut self, video_fmt: *mut ffi::cuvid::CUVIDEOFORMAT) -> i32 {
+        let fmt = unsafe { &*video_fmt };
+
+        match self.configure(fmt) {
+            Ok(decode_surfaces) => {
+                *self.sequence_error.lock().unwrap() = None;
+                decode_surfaces as _
+            }
+            Err(err) => {
+                tracing::error!(decoder = %self.name, "Cannot decode the sequence: {}", err);
+                if let Some(ref events) = self.events {
+                    events.on_decode_error(&err);
+                }
+                // Nothing decodes past this point, wake up the receivers.
+                *self.sequence_error.lock().unwrap() = Some(err);
+                drop(self.sender.take());
+                (fmt.min_num_decode_surfaces + 3) as _
+            }
+        }
+    }
+
+    /// Creates or reconfigures the decoder for a new sequence, returning the
+    /// number of decode surfaces.
+    fn configure(&mut self, fmt: &ffi::cuvid::CUVIDEOFORMAT) -> Result<u64, DecodeError> {
+        tracing::debug!(
+            decoder = %self.name,
+            "Video Input Information
+
+            Status: {},
+            Codec: {}
+            Frame Rate : {}/{}
+            Sequence: {}
+            Coded Size {}x{}
+            Display Area: {}x{}x{}x{}
+            Chroma :{}
+            Bit Depth: {}
+            Minimum Surfaces: {}",
+            if self.decoder.is_null() {
+                "New"
+            } else {
+                "Reconfigure"
+            },
+            fmt.codec,
+            fmt.frame_rate.numerator,
+            fmt.frame_rate.denominator,
+            fmt.progressive_sequence,
+            fmt.coded_width,
+            fmt.coded_height,
+            fmt.display_area.top,
+            fmt.display_area.left,
+            fmt.display_area.bottom,
+            fmt.display_area.right,
+            fmt.chroma_format,
+            fmt.bit_depth_chroma_minus8,
+            fmt.min_num_decode_surfaces,
+        );
+
+        let min_surfaces = fmt.min_num_decode_surfaces + 3;
+
+        let mut decode_caps: ffi::cuvid::CUVIDDECODECAPS = unsafe { std::mem::zeroed() };
+        decode_caps.eCodecType = fmt.codec;
+        decode_caps.eChromaFormat = fmt.chroma_format;
+        decode_caps.nBitDepthMinus8 = fmt.bit_depth_chroma_minus8 as _;
+
+        unsafe {
+            ffi::cuda::cuCtxPushCurrent_v2(self.context.context).err()?;
+            let res = ffi::cuvid::cuvidGetDecoderCaps(&mut decode_caps);
+            ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut()).err()?;
+            res.err()?;
+        }
+
+        if decode_caps.bIsSupported == 0 {
+            return Err(DecodeError::Unsupported(UnsupportedStream::Codec {
+                codec: fmt.codec.into(),
+                chroma_format: fmt.chroma_format.into(),
+                bit_depth: fmt.bit_depth_luma_minus8 + 8,
+            }));
+        }
+
+        if (fmt.coded_width > decode_caps.nMaxWidth) || (fmt.coded_height > decode_caps.nMaxHeight)
+        {
+            return Err(DecodeError::Unsupported(UnsupportedStream::Resolution {
+                width: fmt.coded_width,
+                height: fmt.coded_height,
+                max_width: decode_caps.nMaxWidth,
+                max_height: decode_caps.nMaxHeight,
+            }));
+        }
+        if (fmt.coded_width >> 4) * (fmt.coded_height >> 4) > decode_caps.nMaxMBCount {
+            return Err(DecodeError::Unsupported(
+                UnsupportedStream::MacroblockCount {
+                    macroblocks: (fmt.coded_width >> 4) * (fmt.coded_height >> 4),
+                    max_macroblocks: decode_caps.nMaxMBCount,
+                },
+            ));
+        }
+        let max_size = (
+            fmt.coded_width
+                .max(self.max_size.0)
+                .min(decode_caps.nMaxWidth),
+            fmt.coded_height
+                .max(self.max_size.1)
+                .min(decode_caps.nMaxHeight),
+        );
+        let mut force_recreate = false;
+        if !self.decoder.is_null() {
+            if fmt.coded_width > self.decoder_max_size.0
+                || fmt.coded_height > self.decoder_max_size.1
+            {
+                tracing::debug!(
+                    decoder = %self.name,
+                    "Recreating the decoder for {}x{}, above its maximum of {}x{}",
+                    fmt.coded_width,
+                    fmt.coded_height,
+                    self.decoder_max_size.0,
+                    self.decoder_max_size.1
+                );
+                force_recreate = true;
+            }
+            if self.bit_depth_minus8 != fmt.bit_depth_luma_minus8 {
+                tracing::warn!(
+                    decoder = %self.name,
+                    "Reconfigure Not supported for bit depth change"
+                );
+                force_recreate = true;
+            }
+            if self.chroma_format != fmt.chroma_format.into() {
+                tracing::warn!(
+                    decoder = %self.name,
+                    "Reconfigure Not supported for chroma format change"
+                );
+                force_recreate = true;
+            }
+        }
+        let res_change =
+            !(fmt.coded_width == self.coded_size.0 && fmt.coded_height == self.coded_size.1);
+        let rect_change = match self.video_fmt {
+            Some(ref prev) => {
+                prev.display_area.left != fmt.display_area.left
+                    || prev.display_area.top != fmt.display_area.top
+                    || prev.display_area.right != fmt.display_area.right
+                    || prev.display_area.bottom != fmt.display_area.bottom
+            }
+            None => false,
+        };
+        // A new sequence is as good a point as a keyframe to switch.
+        let size_change = match self.output_size.get_mut().unwrap().take() {
+            Some(size) => {
+                let changed = size != self.requested_size;
+                self.requested_size = size;
+                changed
+            }
+            None => false,
+        };
+
+        self.codec = fmt.codec.into();
+        self.chroma_format = fmt.chroma_format.into();
+        self.bit_depth_minus8 = fmt.bit_depth_luma_minus8;
+        self.bpp = if fmt.bit_depth_luma_minus8 > 0 { 2 } else { 1 };
+
+        // The automatic selection follows NvDecoder.cpp in the Video Codec
+        // SDK samples. NV12 stays the default since it is what the NPP
+        // conversions and OpenCV's GpuMat expect.
+        let preferred = self
+            .requested_output_format
+            .unwrap_or_else(|| native_output_format(self.chroma_format, self.bit_depth_minus8));
+        let supported = |format: VideoSurfaceFormat| {
+            decode_caps.nOutputFormatMask & (1 << (format as u16)) != 0
+        };
+
+        self.output_format = match [
+            preferred,
+            VideoSurfaceFormat::NV12,
+            VideoSurfaceFormat::P016,
+            VideoSurfaceFormat::YUV444,
+            VideoSurfaceFormat::YUV444_16,
+            VideoSurfaceFormat::NV16,
+            VideoSurfaceFormat::P216,
+        ]
+        .iter()
+        .find(|format| supported(**format))
+        {
+            Some(format) => *format,
+            None => return Err(DecodeError::Unsupported(UnsupportedStream::OutputFormat)),
+        };
+        if self.output_format != preferred {
+            tracing::warn!(
+                decoder = %self.name,
+                "Output format {:?} is not supported for this stream, using {:?}",
+                preferred,
+                self.output_format
+            );
+        }
+
+        let format = VideoFormat::from(fmt);
+        let format_changed = self.video_fmt.as_ref().map(VideoFormat::from) != Some(format);
+        self.video_fmt = Some(*fmt);
+        let video_fmt = self.video_fmt.as_ref().unwrap();
+        let held = self.reorder.as_ref().map_or(0, |reorder| reorder.depth());
+        let decode_surfaces = (min_surfaces as u64 + held as u64)
+            .max(self.requested_decode_surfaces.unwrap_or(0) as u64)
+            .min(MAX_DECODE_SURFACES as u64);
+        self.decode_surfaces = decode_surfaces;
+
+        let mut video_decode_create_info: ffi::cuvid::CUVIDDECODECREATEINFO =
+            unsafe { std::mem::zeroed() };
+
+        video_decode_create_info.CodecType = self.codec.into();
+        video_decode_create_info.ChromaFormat = self.chroma_format.into();
+        video_decode_create_info.OutputFormat = self.output_format.into();
+        video_decode_create_info.bitDepthMinus8 = video_fmt.bit_depth_luma_minus8 as _;
+        self.deinterlace_mode = if video_fmt.progressive_sequence != 0 || self.separate_fields {
+            DeinterlaceMode::Weave
+        } else {
+            self.requested_deinterlace
+                .unwrap_or(DeinterlaceMode::Adaptive)
+        };
+        video_decode_create_info.DeinterlaceMode = self.deinterlace_mode.into();
+        video_decode_create_info.ulNumOutputSurfaces =
+            self.requested_output_surfaces.unwrap_or(3) as _;
+        video_decode_create_info.ulCreationFlags =
+            ffi::cuvid::cudaVideoCreateFlags_enum_cudaVideoCreate_PreferCUVID as _;
+        video_decode_create_info.ulNumDecodeSurfaces = decode_surfaces;
+        video_decode_create_info.vidLock = self.lock;
+        video_decode_create_info.ulWidth = video_fmt.coded_width as _;
+        video_decode_create_info.ulHeight = video_fmt.coded_height as _;
+        video_decode_create_info.ulMaxWidth = max_size.0 as _;
+        video_decode_create_info.ulMaxHeight = max_size.1 as _;
+        video_decode_create_info.ulTargetWidth =
+            (video_fmt.display_area.right - video_fmt.display_area.left) as _;
+        video_decode_create_info.ulTargetHeight =
+            (video_fmt.display_area.bottom - video_fmt.display_area.top) as _;
+        video_decode_create_info.ulIntraDecodeOnly = if self.keyframe_only { 1 } else { 0 };
+        // Left zeroed, NVDEC would scale the whole coded picture instead.
+        video_decode_create_info.display_area.left = video_fmt.display_area.left as _;
+        video_decode_create_info.display_area.top = video_fmt.display_area.top as _;
+        video_decode_create_info.display_area.right = video_fmt.display_area.right as _;
+        video_decode_create_info.display_area.bottom = video_fmt.display_area.bottom as _;
+
+        if self.requested_size.0 > 0 && self.requested_size.1 > 0 {
+            self.out_size = self.requested_size;
+            video_decode_create_info.ulTargetWidth = self.out_size.0 as _;
+            video_decode_create_info.ulTargetHeight = self.out_size.1 as _;
+        } else {
+            self.out_size.0 = (video_fmt.display_area.right - video_fmt.display_area.left) as _;
+            self.out_size.1 = (video_fmt.display_area.bottom - video_fmt.display_area.top) as _;
+        }
+        self.coded_size = (video_fmt.coded_width, video_fmt.coded_height);
+        unsafe {
+            ffi::cuda::cuCtxPushCurrent_v2(self.context.context).err()?;
+            if force_recreate {
+                ffi::cuvid::cuvidDestroyDecoder(self.decoder);
+                SESSIONS.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                self.decoder = std::ptr::null_mut();
+            }
+
+            let (created, res) = if self.decoder.is_null() {
+                let res = ffi::cuvid::cuvidCreateDecoder(
+                    &mut self.decoder,
+                    &mut video_decode_create_info,
+                );
+                if res.ok() {
+                    self.decoder_max_size = max_size;
+                    SESSIONS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+                (true, res)
+            } else if res_change || rect_change || size_change {
+                (false, self.reconfigure())
+            } else {
+                (false, ffi::cuda::cudaError_enum_CUDA_SUCCESS)
+            };
+            ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut()).err()?;
+            if created {
+                res.err().map_err(|res| creation_error(res, fmt))?;
+            }
+            res.err()?;
+        }
+
+        if format_changed {
+            if let Some(ref on_format_change) = self.on_format_change {
+                on_format_change(&format);
+            }
+            if let Some(ref events) = self.events {
+                events.on_sequence_change(&format);
+            }
+        }
+
+        Ok(decode_surfaces)
+    }
+
+    /// Applies the current coded size, crop and `out_size` to the decoder,
+    /// which keeps its surfaces. The context must be current.
+    unsafe fn reconfigure(&mut self) -> ffi::cuda::CUresult {
+        let video_fmt = match self.video_fmt {
+            Some(ref video_fmt) => video_fmt,
+            None => return ffi::cuda::cudaError_enum_CUDA_ERROR_NOT_INITIALIZED,
+        };
+
+        // Same as the creation parameters, only the sizes and crop change.
+        let mut video_decode_reconfigure_info: ffi::cuvid::CUVIDRECONFIGUREDECODERINFO =
+            std::mem::zeroed();
+        video_decode_reconfigure_info.ulWidth = video_fmt.coded_width as _;
+        video_decode_reconfigure_info.ulHeight = video_fmt.coded_height as _;
+        video_decode_reconfigure_info.ulTargetWidth = self.out_size.0 as _;
+        video_decode_reconfigure_info.ulTargetHeight = self.out_size.1 as _;
+        video_decode_reconfigure_info.ulNumDecodeSurfaces = self.decode_surfaces as _;
+        video_decode_reconfigure_info.display_area.left = video_fmt.display_area.left as _;
+        video_decode_reconfigure_info.display_area.top = video_fmt.display_area.top as _;
+        video_decode_reconfigure_info.display_area.right = video_fmt.display_area.right as _;
+        video_decode_reconfigure_info.display_area.bottom = video_fmt.display_area.bottom as _;
+
+        ffi::cuvid::cuvidReconfigureDecoder(self.decoder, &mut video_decode_reconfigure_info)
+    }
+
+    /// Switches to the output size set with `Decoder::set_output_size` once
+    /// at a keyframe no frame is held, as the surfaces change size.
+    fn apply_output_size(&mut self, params: &ffi::cuvid::CUVIDPICPARAMS) {
+        if !picture::is_keyframe(self.codec, params) || self.surfaces.any() {
+            return;
+        }
+        let size = match self.output_size.lock().unwrap().take() {
+            Some(size) => size,
+            None => return,
+        };
+        let video_fmt = match self.video_fmt {
+            Some(ref video_fmt) => *video_fmt,
+            None => return,
+        };
+
+        let previous = (self.requested_size, self.out_size);
+        self.requested_size = size;
+        self.out_size = if size.0 > 0 && size.1 > 0 {
+            size
+        } else {
+            (
+                (video_fmt.display_area.right - video_fmt.display_area.left) as _,
+                (video_fmt.display_area.bottom - video_fmt.display_area.top) as _,
+            )
+        };
+
+        unsafe {
+            if ffi::cuda::cuCtxPushCurrent_v2(self.context.context).ok() {
+                let reconfigured = self.reconfigure().ok();
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+                if reconfigured {
+                    tracing::debug!(
+                        decoder = %self.name,
+                        "Output size changed to {}x{}",
+                        self.out_size.0,
+                        self.out_size.1
+                    );
+                    return;
+                }
+            }
+        }
+
+        tracing::error!(
+            decoder = %self.name,
+            "Failed to change the output size to {}x{}",
+            self.out_size.0,
+            self.out_size.1
+        );
+        self.requested_size = previous.0;
+        self.out_size = previous.1;
+    }
+
+    fn picture_decode_cb(&mut self, pic_params: *mut ffi::cuvid::CUVIDPICPARAMS) -> i32 {
+        if self.decoder.is_null() {
+            tracing::error!(
+                decoder = %self.name,
+                "picture_decode_cb called but decoder is not initialized."
+            );
+            return 0;
+        }
+        let pic_idx = unsafe {
+            let pic_params = unsafe { &*pic_params };
+            pic_params.CurrPicIdx as usize
+        };
+        if pic_idx >= MAX_DECODE_SURFACES {
+            tracing::error!(decoder = %self.name, "picture index {} out of range", pic_idx);
+            return 0;
+        }
+        if self.skipped.len() <= pic_idx {
+            self.skipped.resize(pic_idx + 1, None);
+        }
+        let params = unsafe { &*pic_params };
+        let keyframe = picture::is_keyframe(self.codec, params);
+        if keyframe {
+            if let Some(keyframe_only) = self.pending_keyframe_only.lock().unwrap().take() {
+                self.skip_to_keyframes = keyframe_only;
+            }
+        }
+        let skip = *self.frame_skip.lock().unwrap();
+        let decimated = self.decimator.as_ref().is_some_and(|decimator| {
+            let reference = params.ref_pic_flag != 0 || params.intra_pic_flag != 0;
+            !decimator.lock().unwrap().decodes(keyframe, reference)
+        });
+        // Nothing refers to the picture, or only pictures skipped as well,
+        // its surface is left untouched.
+        self.skipped[pic_idx] = if decimated {
+            Some(DropCause::Decimated)
+        } else if (self.skip_to_keyframes && !keyframe) || skip.skips(params, self.receiver.len()) {
+            Some(DropCause::Skipped)
+        } else {
+            None
+        };
+        if self.skipped[pic_idx].is_some() {
+            return 1;
+        }
+        self.apply_output_size(unsafe { &*pic_params });
+        let start = std::time::Instant::now();
+        let mut warned = false;
+        let mut backoff = std::time::Duration::from_micros(100);
+        let starved = self.is_frame_in_use(pic_idx);
+        while self.is_frame_in_use(pic_idx) {
+            if start.elapsed() > std::time::Duration::from_secs(5) && !warned {
+                tracing::warn!(
+                    decoder = %self.name,
+                    "Waited way too long for frame to become free."
+                );
+                warned = true;
+            }
+            // Back off so that consumers holding frames for long don't keep
+            // a core busy polling.
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(std::time::Duration::from_millis(2));
+        }
+        if start.elapsed() > std::time::Duration::from_secs(5) {
+            tracing::warn!(
+                decoder = %self.name,
+                "Waited way {}ms for frame to become free.",
+                start.elapsed().as_millis()
+            );
+        }
+        if starved {
+            if let Some(ref events) = self.events {
+                events.on_surface_starvation(start.elapsed());
+            }
+        }
+        if self.decoder.is_null() {
+            tracing::debug!(
+                decoder = %self.name,
+                "decoder was dropped while waiting for frame in use."
+            );
+            return 0;
+        }
+
+        if self.picture_types.len() <= pic_idx {
+            self.picture_types.resize(pic_idx + 1, PictureType::I);
+        }
+        self.picture_types[pic_idx] = PictureType::from_params(unsafe { &*pic_params });
+
+        unsafe {
+            if !ffi::cuda::cuCtxPushCurrent_v2(self.context.context).ok() {
+                return 0;
+            }
+            let res = ffi::cuvid::cuvidDecodePicture(self.decoder, pic_params);
+            if !res.ok() {
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+                if let Some(ref events) = self.events {
+                    events.on_decode_error(&DecodeError::Cuda(res));
+                }
+                return 0;
+            }
+            // low latency option
+            if !ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut()).ok() {
+                return 0;
+            }
+        }
+
+        1
+    }
+
+    fn picture_display_cb(&mut self, display_info: *mut ffi::cuvid::CUVIDPARSERDISPINFO) -> i32 {
+        if display_info.is_null() {
+            if let Some(frames) = self.reorder.as_mut().map(ReorderBuffer::drain) {
+                if self.sender.is_some() {
+                    for frame in frames {
+                        self.deliver(frame);
+                    }
+                }
+            }
+            self.eos.store(true, std::sync::atomic::Ordering::SeqCst);
+            drop(self.sender.take());
+            if let Some(ref events) = self.events {
+                events.on_eos();
+            }
+            return 1;
+        }
+        if self.sender.is_none() {
+            return 1;
+        }
+        let display_info = unsafe { &*display_info };
+        if let Some(&Some(cause)) = self.skipped.get(display_info.picture_index as usize) {
+            self.record_drop(cause, display_info.timestamp);
+            return 1;
+        }
+        if self.paused.load(std::sync::atomic::Ordering::SeqCst) {
+            // Still decoded, so that references stay intact for resuming.
+            self.record_drop(DropCause::Paused, display_info.timestamp);
+            return 1;
+        }
+        let video_processing_parameters = {
+            let mut video_processing_parameters: ffi::cuvid::CUVIDPROCPARAMS =
+                unsafe { std::mem::zeroed() };
+            video_processing_parameters.progressive_frame = display_info.progressive_frame;
+            video_processing_parameters.second_field = display_info.repeat_first_field + 1;
+            video_processing_parameters.top_field_first = display_info.top_field_first;
+            video_processing_parameters.unpaired_field =
+                (display_info.repeat_first_field < 0) as i32;
+
+            video_processing_parameters
+        };
+
+        // The surface stays reserved from now until the frame is dropped.
+        self.set_frame_status(display_info.picture_index as usize, true);
+        let sei = self
+            .pending_sei
+            .get_mut(display_info.picture_index as usize)
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let picture_type = self
+            .picture_types
+            .get(display_info.picture_index as usize)
+            .copied()
+            .unwrap_or(PictureType::I);
+        if let Some(orientation) = sei.iter().rev().find_map(|m| m.display_orientation()) {
+            self.orientation = orientation;
+        }
+        let orientation = self.orientation;
+        let frame_rate = self.video_fmt.as_ref().map_or((0, 0), |fmt| {
+            (fmt.frame_rate.numerator, fmt.frame_rate.denominator)
+        });
+        // Repeated fields, for pulldown or frame doubling, lengthen the
+        // display, a lone field shortens it.
+        let fields = 2 + display_info.repeat_first_field;
+
+        let fields_apart = self.deinterlace_mode == DeinterlaceMode::Bob || self.separate_fields;
+        if fields_apart && display_info.progressive_frame == 0 {
+            // One frame per field, the second half a frame later.
+            let field_duration = fields_duration(frame_rate, 1).unwrap_or(0);
+            let top_first = display_info.top_field_first != 0;
+
+            for field in 0..2 {
+                let mut parameters = video_processing_parameters;
+                parameters.second_field = field;
+                let delivered = self.output(PreparedFrame {
+                    index: display_info.picture_index,
+                    parameters,
+                    timestamp: display_info.timestamp + field as i64 * field_duration,
+                    sei: if field == 0 { sei.clone() } else { Vec::new() },
+                    picture_type,
+                    orientation,
+                    duration: fields_duration(frame_rate, if field == 0 { 1 } else { fields - 1 }),
+                    field: Some(if (field == 0) == top_first {
+                        Field::Top
+                    } else {
+                        Field::Bottom
+                    }),
+                    split: self.separate_fields,
+                    release: field == 1,
+                });
+
+                if !delivered {
+                    self.set_frame_status(display_info.picture_index as usize, false);
+                    return 0;
+                }
+            }
+
+            return 1;
+        }
+
+        let delivered = self.output(PreparedFrame {
+            index: display_info.picture_index,
+            parameters: video_processing_parameters,
+            timestamp: display_info.timestamp,
+            sei,
+            picture_type,
+            orientation,
+            duration: fields_duration(frame_rate, fields),
+            field: None,
+            split: false,
+            release: true,
+        });
+
+        if !delivered {
+            self.set_frame_status(display_info.picture_index as usize, false);
+            return 0;
+        }
+        return 1;
+    }
+
+    /// Delivers `frame`, or the earliest held one in timestamp order with
+    /// `DecoderBuilder::pts_order`.
+    fn output(&mut self, frame: PreparedFrame) -> bool {
+        let timestamp = frame.timestamp();
+        let frame = match self.reorder {
+            Some(ref mut reorder) => match reorder.push(timestamp, frame) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return true,
+                Err(late) => {
+                    self.drop_frame(&late, DropCause::OutOfOrder);
+                    return true;
+                }
+            },
+            None => return self.deliver(frame),
+        };
+
+        let (index, release) = (frame.index, frame.release);
+        let delivered = self.deliver(frame);
+        // The caller only releases the surface of the frame it output.
+        if !delivered && release {
+            self.set_frame_status(index as usize, false);
+        }
+        delivered
+    }
+
+    /// Hands `frame` to the consumer, the `on_frame` callback or else the
+    /// channel following the overflow policy. False once nobody receives
+    /// the frames anymore.
+    fn deliver(&mut self, frame: PreparedFrame) -> bool {
+        if let Some(mut on_frame) = self.on_frame.take() {
+            if self.push(frame, &mut on_frame) == FrameFlow::Continue {
+                self.on_frame = Some(on_frame);
+            }
+            return true;
+        }
+
+        let sender = self.sender.as_ref().unwrap();
+        match self.overflow {
+            DropPolicy::Block => sender.send(frame).is_ok(),
+            DropPolicy::DropNewest => {
+                // The first of two bobbed fields only goes in with room for
+                // the second one, which releases the surface. Only this
+                // thread sends, so the room can't shrink meanwhile.
+                let room = if frame.release { 1 } else { 2 };
+                if sender
+                    .capacity()
+                    .is_some_and(|capacity| sender.len() + room > capacity)
+                {
+                    self.drop_frame(&frame, DropCause::Overflow);
+                    return true;
+                }
+                sender.send(frame).is_ok()
+            }
+            DropPolicy::DropOldest => {
+                let mut frame = frame;
+                loop {
+                    match sender.try_send(frame) {
+                        Err(flume::TrySendError::Full(rejected)) => {
+                            if let Ok(oldest) = self.receiver.try_recv() {
+                                self.drop_frame(&oldest, DropCause::Overflow);
+                            }
+                            frame = rejected;
+                        }
+                        res => return res.is_ok(),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Maps `frame` on the parsing thread and passes it to `on_frame`.
+    fn push(&self, frame: PreparedFrame, on_frame: &mut FrameCallback) -> FrameFlow {
+        let frames = FramesIter {
+            inner: self,
+            context: None,
+            frame_timeout: None,
+        };
+        if !frames.keep(&frame) {
+            return FrameFlow::Continue;
+        }
+
+        match frames.map_frame(frame) {
+            Ok(frame) => on_frame(frame),
+            // Dropped and counted already.
+            Err(_) => FrameFlow::Continue,
+        }
+    }
+
+    fn sei_msg_cb(&mut self, sei_info: *mut ffi::cuvid::CUVIDSEIMESSAGEINFO) -> i32 {
+        if sei_info.is_null() {
+            return 1;
+        }
+        let sei_info = unsafe { &*sei_info };
+        let index = sei_info.picIdx as usize;
+
+        if self.pending_sei.len() <= index {
+            self.pending_sei.resize_with(index + 1, Vec::new);
+        }
+        self.pending_sei[index] = sei::collect(sei_info);
+
+        1
+    }
+
+    fn operating_point_cb(&self, op_info: *mut ffi::cuvid::CUVIDOPERATINGPOINTINFO) -> i32 {
+        if op_info.is_null() {
+            return -1;
+        }
+        let points = layer::operating_points(unsafe { &*op_info });
+        let (index, all_layers) = self.operating_point;
+        let selected = layer::select(points.len(), index, all_layers);
+        *self.operating_points.lock().unwrap() = points;
+
+        selected
+    }
+}
+
+/// Why `cuvidCreateDecoder` failed with `res`, which is all the driver
+/// tells about a session limit or a codec the caps did not rule out.
+fn creation_error(res: ffi::cuda::CUresult, fmt: &ffi::cuvid::CUVIDEOFORMAT) -> DecodeError {
+    let sessions = SESSIONS.load(std::sync::atomic::Ordering::SeqCst);
+    match res {
+        // Other sessions are open, so the memory is most likely taken by
+        // them or the driver's limit reached.
+        ffi::cuda::cudaError_enum_CUDA_ERROR_OUT_OF_MEMORY if sessions > 0 => {
+            DecodeError::SessionsExhausted { sessions }
+        }
+        ffi::cuda::cudaError_enum_CUDA_ERROR_NOT_SUPPORTED => {
+            DecodeError::Unsupported(UnsupportedStream::Codec {
+                codec: fmt.codec.into(),
+                chroma_format: fmt.chroma_format.into(),
+                bit_depth: fmt.bit_depth_luma_minus8 + 8,
+            })
+        }
+        res => DecodeError::Cuda(res),
+    }
+}
+
+fn native_output_format(
+    chroma_format: VideoChromaFormat,
+    bit_depth_minus8: u8,
+) -> VideoSurfaceFormat {
+    match (chroma_format, bit_depth_minus8) {
+        (VideoChromaFormat::YUV444, 0) => VideoSurfaceFormat::YUV444,
+        (VideoChromaFormat::YUV444, _) => VideoSurfaceFormat::YUV444_16,
+        (VideoChromaFormat::YUV422, 0) => VideoSurfaceFormat::NV16,
+        (VideoChromaFormat::YUV422, _) => VideoSurfaceFormat::P216,
+        (_, 0) => VideoSurfaceFormat::NV12,
+        (_, _) => VideoSurfaceFormat::P016,
+    }
+}
+
+pub struct FramesIter<'a, 'b> {
+    inner: &'a Inner,
+    context: Option<&'b CuContext>,
+    pub(crate) frame_timeout: Option<Duration>,
+}
+
+impl<'a, 'b> FramesIter<'a, 'b> {
+    /// Like `next`, but tells why no frame was returned. A `Corrupted`
+    /// picture does not end the stream, the following frames can still be
+    /// received.
+    pub fn try_next(&mut self) -> Result<GpuFrame, DecodeError> {
+        let inner = self.inner;
+        let _entered = inner.span.enter();
+        let frame = loop {
+            let frame = match self.frame_timeout {
+                Some(timeout) => match self.inner.receiver.recv_timeout(timeout) {
+                    Ok(frame) => frame,
+                    Err(flume::RecvTimeoutError::Timeout) => return Err(DecodeError::Timeout),
+                    Err(flume::RecvTimeoutError::Disconnected) => return Err(self.disconnected()),
+                },
+                None => self
+                    .inner
+                    .receiver
+                    .recv()
+                    .map_err(|_| self.disconnected())?,
+            };
+
+            if self.keep(&frame) {
+                break frame;
+            }
+        };
+
+        self.map_frame(frame)
+    }
+
+    /// Like `try_next`, but copies the frame into memory of its own and
+    /// releases the decoder surface right away, so that it can be held for
+    /// as long as needed. The memory comes from the frame allocator of the
+    /// decoder, or is allocated in its context without one. Only NV12
+    /// frames are copied.
+    pub fn try_next_copied(&mut self) -> Result<DeviceFrame, DecodeError> {
+        let frame = self.try_next()?;
+        let current = self.context.unwrap_or(&self.inner.context).push()?;
+        let mut copy = match self.inner.frame_allocator {
+            Some(ref allocator) => allocator
+                .lock()
+                .unwrap()
+                .allocate(frame.width, frame.height)?,
+            None => DeviceFrame::new(frame.width, frame.height)?,
+        };
+        frame.copy_into(&mut copy, &current, None)?;
+
+        Ok(copy)
+    }
+
+    /// Like `try_next`, but tells the end of the stream from a decoder that
+    /// stopped without one. Pictures dropped as corrupted are passed over.
+    pub fn recv(&mut self) -> Result<GpuFrame, FrameRecvError> {
+        loop {
+            return match self.try_next() {
+                Ok(frame) => Ok(frame),
+                Err(DecodeError::Corrupted { .. }) => continue,
+                Err(DecodeError::Timeout) => Err(FrameRecvError::Timeout),
+                Err(DecodeError::Eos)
+                    if self.inner.eos.load(std::sync::atomic::Ordering::SeqCst) =>
+                {
+                    Err(FrameRecvError::Eos)
+                }
+                Err(DecodeError::Eos) => Err(FrameRecvError::Disconnected),
+                Err(err) => Err(FrameRecvError::Decode(err)),
+            };
+        }
+    }
+
+    /// Why no more frames arrive.
+    pub(crate) fn disconnected(&self) -> DecodeError {
+        self.inner
+            .sequence_error
+            .lock()
+            .unwrap()
+            .unwrap_or(DecodeError::Eos)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn receiver(&self) -> &'a flume::Receiver<PreparedFrame> {
+        &self.inner.receiver
+    }
+
+    /// Whether `frame` passes the decimation, dropping it otherwise.
+    pub(crate) fn keep(&self, frame: &PreparedFrame) -> bool {
+        match self.inner.decimator {
+            Some(ref decimator) if !decimator.lock().unwrap().keep(frame.timestamp()) => {
+                self.inner.drop_frame(frame, DropCause::Decimated);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Maps a received frame. On error it is dropped and its surface
+    /// released.
+    pub(crate) fn map_frame(&self, mut frame: PreparedFrame) -> Result<GpuFrame, DecodeError> {
+        let _entered = self.inner.span.enter();
+        let mut dp_src_frame: CUdeviceptr = 0;
+        let mut n_src_pitch = 0u32;
+        let mut status = DecodeStatus::Success;
+        let context = self.context.unwrap_or(&self.inner.context).context;
+
+        unsafe {
+            if let Err(err) = ffi::cuda::cuCtxPushCurrent_v2(context).err() {
+                tracing::error!(decoder = %self.inner.name, "Failed to push current context.");
+                self.inner.drop_frame(&frame, DropCause::MapFailed);
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+                return Err(DecodeError::Cuda(err));
+            }
+
+            let mut decode_status: ffi::cuvid::CUVIDGETDECODESTATUS = std::mem::zeroed();
+
+            if ffi::cuvid::cuvidGetDecodeStatus(self.inner.decoder, frame.index, &mut decode_status)
+                .ok()
+            {
+                status = match decode_status.decodeStatus {
+                    ffi::cuvid::cuvidDecodeStatus_enum_cuvidDecodeStatus_Error => {
+                        DecodeStatus::Error
+                    }
+                    ffi::cuvid::cuvidDecodeStatus_enum_cuvidDecodeStatus_Error_Concealed => {
+                        DecodeStatus::Concealed
+                    }
+                    _ => DecodeStatus::Success,
+                };
+                if status != DecodeStatus::Success {
+                    frame_warn!(self.inner, "Decoding error occured");
+                    if let Some(ref events) = self.inner.events {
+                        events.on_decode_error(&DecodeError::Corrupted {
+                            timestamp: frame.timestamp(),
+                        });
+                    }
+                }
+                if self.inner.error_policy.drops(status) {
+                    self.inner.drop_frame(&frame, DropCause::Corrupted);
+                    ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+                    return Err(DecodeError::Corrupted {
+                        timestamp: frame.timestamp(),
+                    });
+                }
+            }
+
+            // tracing::info!("{}: {}", context.is_some(), frame.index);
+            if let Err(err) = ffi::cuvid::cuvidMapVideoFrame64(
+                self.inner.decoder,
+                frame.index,
+                &mut dp_src_frame,
+                &mut n_src_pitch,
+                &mut frame.parameters,
+            )
+            .err()
+            {
+                tracing::error!(decoder = %self.inner.name, "Failed to map video frame: {}", err);
+                self.inner.drop_frame(&frame, DropCause::MapFailed);
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+                return Err(DecodeError::Cuda(err));
+            }
+        }
+
+        let (mut ptr, mut pitch, mut size) = (dp_src_frame, n_src_pitch, self.inner.out_size);
+        if frame.split {
+            // Every other line of the woven frame, in every plane.
+            if frame.field == Some(Field::Bottom) {
+                ptr += pitch as CUdeviceptr;
+            }
+            pitch *= 2;
+            size.1 /= 2;
+        }
+
+        let (black, frozen) = match self.inner.detector {
+            Some(ref detector) if self.inner.output_format == VideoSurfaceFormat::NV12 => detector
+                .lock()
+                .unwrap()
+                .check(ptr, pitch, size)
+                .unwrap_or_else(|err| {
+                    tracing::warn!(
+                        decoder = %self.inner.name,
+                        "Failed to check for a dead frame: {:?}",
+                        err
+                    );
+                    (false, false)
+                }),
+            _ => (false, false),
+        };
+        unsafe {
+            ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+        }
+
+        let timestamp = match self.inner.smoother {
+            Some(ref smoother) => smoother.lock().unwrap().push(frame.timestamp()),
+            None => frame.timestamp(),
+        };
+        let interval = self.inner.intervals.lock().unwrap().push(timestamp);
+        let format = self.inner.video_fmt.as_ref().map(VideoFormat::from);
+
+        let frame = GpuFrame {
+            width: size.0,
+            height: size.1,
+            ptr,
+            pitch,
+            timestamp,
+            interval,
+            duration: frame.duration,
+            frame_rate: format
+                .map(|format| format.frame_rate)
+                .filter(|&(num, den)| num > 0 && den > 0)
+                .map(|(num, den)| match frame.field {
+                    Some(_) => (num * 2, den),
+                    None => (num, den),
+                }),
+            format: self.inner.output_format,
+            bit_depth: self.inner.bit_depth_minus8 + 8,
+            video_signal: self.inner.video_signal(),
+            coded_size: format.map_or((0, 0), |format| format.coded_size),
+            display_area: format.map_or(Rect::default(), |format| format.display_area),
+            sample_aspect_ratio: format.map_or((1, 1), |format| format.sample_aspect_ratio(size)),
+            orientation: frame.orientation,
+            field: frame.field,
+            sei: std::mem::take(&mut frame.sei),
+            picture_type: frame.picture_type,
+            decode_status: status,
+            black,
+            frozen,
+            release: frame.release,
+            decoder: self.inner.decoder,
+            idx: frame.index,
+            surfaces: Arc::clone(&self.inner.surfaces),
+            mapped: dp_src_frame,
+            context,
+        };
+
+        Ok(frame)
+    }
+}
+
+impl<'a, 'b> Iterator for FramesIter<'a, 'b> {
+    type Item = GpuFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().ok()
+    }
+}
+
+pub unsafe extern "C" fn handle_video_sequence_proc(
+    user_data: *mut std::os::raw::c_void,
+    video_format: *mut ffi::cuvid::CUVIDEOFORMAT,
+) -> i32 {
+    let decoder = user_data as *mut Inner;
+    let decoder = &mut *decoder;
+    let span = decoder.span.clone();
+    let _entered = span.enter();
+
+    decoder.sequence_cb(video_format)
+}
+
+pub unsafe extern "C" fn handle_picture_decode_proc(
+    user_data: *mut std::os::raw::c_void,
+    pic_params: *mut ffi::cuvid::CUVIDPICPARAMS,
+) -> i32 {
+    let decoder = user_data as *mut Inner;
+    let decoder = &mut *decoder;
+    let span = decoder.span.clone();
+    let _entered = span.enter();
+
+    decoder.picture_decode_cb(pic_params)
+}
+
+pub unsafe extern "C" fn handle_picture_display_proc(
+    user_data: *mut std::os::raw::c_void,
+    display_info: *mut ffi::cuvid::CUVIDPARSERDISPINFO,
+) -> i32 {
+    let decoder = user_data as *mut Inner;
+    let decoder = &mut *decoder;
+    let span = decoder.span.clone();
+    let _entered = span.enter();
+
+    decoder.picture_display_cb(display_info)
+}
+
+pub unsafe extern "C" fn handle_sei_msg_proc(
+    user_data: *mut std::os::raw::c_void,
+    sei_info: *mut ffi::cuvid::CUVIDSEIMESSAGEINFO,
+) -> i32 {
+    let decoder = user_data as *mut Inner;
+    let decoder = &mut *decoder;
+    let span = decoder.span.clone();
+    let _entered = span.enter();
+
+    decoder.sei_msg_cb(sei_info)
+}
+
+pub unsafe extern "C" fn handle_operating_point_proc(
+    user_data: *mut std::os::raw::c_void,
+    op_info: *mut ffi::cuvid::CUVIDOPERATINGPOINTINFO,
+) -> i32 {
+    let decoder = user_data as *mut Inner;
+    let decoder = &*decoder;
+    let _entered = decoder.span.enter();
+
+    decoder.operating_point_cb(op_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surfaces_beyond_64() {
+        let surfaces = Surfaces::default();
+        assert!(!surfaces.any());
+
+        surfaces.set(3, true);
+        surfaces.set(200, true);
+        assert_eq!(surfaces.count(), 2);
+        assert!(surfaces.is_set(200));
+        assert!(!surfaces.is_set(136));
+
+        surfaces.set(3, false);
+        assert!(surfaces.any());
+        surfaces.set(200, false);
+        assert!(!surfaces.any());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(fields_duration((25, 1), 2), Some(400_000));
+        assert_eq!(fields_duration((30000, 1001), 3), Some(500_500));
+        assert_eq!(fields_duration((25, 1), 1), Some(200_000));
+        assert_eq!(fields_duration((25, 1), 0), None);
+        assert_eq!(fields_duration((0, 1), 2), None);
+    }
+
+    #[test]
+    fn frames_are_send() {
+        fn send<T: Send>() {}
+        send::<GpuFrame>();
+    }
+
+    #[test]
+    fn error_policies() {
+        use self::DecodeStatus::*;
+
+        let dropped = |policy: ErrorPolicy| {
+            [Success, Concealed, Error]
+                .iter()
+                .map(|&status| policy.drops(status))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dropped(ErrorPolicy::EmitWithFlag), [false, false, false]);
+        assert_eq!(dropped(ErrorPolicy::DropConcealed), [false, true, false]);
+        assert_eq!(dropped(ErrorPolicy::DropAnyError), [false, true, true]);
+    }
+
+    #[test]
+    fn decimation_rates() {
+        let builder = |fps| Decoder::builder(Codec::H264).decimate(Decimation::MaxFps(fps));
+        assert!(builder(5.0).validate().is_ok());
+        assert!(builder(0.0).validate().is_err());
+        assert!(builder(-1.0).validate().is_err());
+        assert!(builder(f64::NAN).validate().is_err());
+        assert!(builder(f64::INFINITY).validate().is_err());
+    }
+}
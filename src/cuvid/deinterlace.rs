@@ -0,0 +1,26 @@
+use super::ffi;
+
+/// How interlaced pictures are turned into frames.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum DeinterlaceMode {
+    /// Both fields woven into one frame, no processing.
+    Weave = ffi::cuvid::cudaVideoDeinterlaceMode_enum_cudaVideoDeinterlaceMode_Weave,
+    /// Every field becomes a frame of its own, doubling the frame rate.
+    Bob = ffi::cuvid::cudaVideoDeinterlaceMode_enum_cudaVideoDeinterlaceMode_Bob,
+    /// Motion adaptive deinterlacing into one frame per picture.
+    Adaptive = ffi::cuvid::cudaVideoDeinterlaceMode_enum_cudaVideoDeinterlaceMode_Adaptive,
+}
+
+impl From<DeinterlaceMode> for ffi::cuvid::cudaVideoDeinterlaceMode {
+    fn from(mode: DeinterlaceMode) -> Self {
+        mode as ffi::cuvid::cudaVideoDeinterlaceMode
+    }
+}
+
+/// One of the two fields of an interlaced picture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    Top,
+    Bottom,
+}
@@ -0,0 +1,145 @@
+use std::convert::TryInto;
+
+use super::super::cuda::mem::DeviceBuffer;
+use super::super::NppResult;
+use super::{ffi, CUdeviceptr};
+use quality::Error;
+
+/// Limits of the dead feed detection, measured on the 8 bit luma plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeadFrameThresholds {
+    /// Mean luma at or below which a frame counts as black. Limited range
+    /// black is 16.
+    pub black_luma: f64,
+    /// Mean absolute luma difference to the previous frame at or below
+    /// which a frame counts as frozen.
+    pub frozen_diff: f64,
+}
+
+impl Default for DeadFrameThresholds {
+    fn default() -> Self {
+        DeadFrameThresholds {
+            black_luma: 20.0,
+            frozen_diff: 0.25,
+        }
+    }
+}
+
+/// Per-frame luma mean and difference to the previous frame, two NPP
+/// reductions and a single read back.
+pub(crate) struct DeadFrameDetector {
+    thresholds: DeadFrameThresholds,
+    scratch: Option<DeviceBuffer>,
+    results: Option<DeviceBuffer>,
+    previous: Option<(DeviceBuffer, (u32, u32))>,
+}
+
+impl DeadFrameDetector {
+    pub(crate) fn new(thresholds: DeadFrameThresholds) -> Self {
+        DeadFrameDetector {
+            thresholds,
+            scratch: None,
+            results: None,
+            previous: None,
+        }
+    }
+
+    /// Forgets the previous frame, e.g. after a flush.
+    pub(crate) fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Whether the luma plane at `luma` is black and whether it is frozen.
+    /// The context owning the plane must be current.
+    pub(crate) fn check(
+        &mut self,
+        luma: CUdeviceptr,
+        pitch: u32,
+        size: (u32, u32),
+    ) -> Result<(bool, bool), Error> {
+        let stream_ctx = super::super::npp_stream_context(None)?;
+        let roi = ffi::npp::NppiSize {
+            width: size.0 as _,
+            height: size.1 as _,
+        };
+
+        let (mut mean_size, mut error_size) = (0, 0);
+        unsafe {
+            ffi::npp::nppiMeanGetBufferHostSize_8u_C1R_Ctx(roi, &mut mean_size, stream_ctx)
+                .err()?;
+            ffi::npp::nppiAverageErrorGetBufferHostSize_8u_C1R_Ctx(
+                roi,
+                &mut error_size,
+                stream_ctx,
+            )
+            .err()?;
+        }
+        let scratch_size = (mean_size as usize).max(error_size as usize);
+        if self.scratch.as_ref().is_none_or(|s| s.len() < scratch_size) {
+            self.scratch = Some(DeviceBuffer::new(scratch_size)?);
+        }
+        if self.results.is_none() {
+            self.results = Some(DeviceBuffer::new(2 * std::mem::size_of::<f64>())?);
+        }
+        let scratch = self.scratch.as_ref().unwrap().as_ptr() as *mut ffi::npp::Npp8u;
+        let results = self.results.as_ref().unwrap();
+        let mean_ptr = results.as_ptr() as *mut ffi::npp::Npp64f;
+
+        if self.previous.as_ref().is_some_and(|p| p.1 != size) {
+            self.previous = None;
+        }
+        let compared = self.previous.is_some();
+
+        unsafe {
+            ffi::npp::nppiMean_8u_C1R_Ctx(
+                luma as *const ffi::npp::Npp8u,
+                pitch as _,
+                roi,
+                scratch,
+                mean_ptr,
+                stream_ctx,
+            )
+            .err()?;
+
+            if let Some((ref previous, _)) = self.previous {
+                ffi::npp::nppiAverageError_8u_C1R_Ctx(
+                    luma as *const ffi::npp::Npp8u,
+                    pitch as _,
+                    previous.as_ptr() as *const ffi::npp::Npp8u,
+                    size.0 as _,
+                    roi,
+                    mean_ptr.add(1),
+                    scratch,
+                    stream_ctx,
+                )
+                .err()?;
+            }
+        }
+
+        if self.previous.is_none() {
+            self.previous = Some((DeviceBuffer::new((size.0 * size.1) as usize)?, size));
+        }
+        let previous = self.previous.as_ref().unwrap().0.as_ptr();
+        unsafe {
+            ffi::npp::nppiCopy_8u_C1R_Ctx(
+                luma as *const ffi::npp::Npp8u,
+                pitch as _,
+                previous as *mut ffi::npp::Npp8u,
+                size.0 as _,
+                roi,
+                stream_ctx,
+            )
+            .err()?;
+        }
+
+        let mut bytes = [0u8; 16];
+        results.copy_to_host(&mut bytes, None)?;
+        let mean = f64::from_ne_bytes(bytes[..8].try_into().unwrap());
+        let diff = f64::from_ne_bytes(bytes[8..].try_into().unwrap());
+
+        Ok((
+            mean <= self.thresholds.black_luma,
+            compared && diff <= self.thresholds.frozen_diff,
+        ))
+    }
+}
@@ -0,0 +1,176 @@
+use std::ffi::CStr;
+use std::fmt;
+
+use super::{ffi, Codec, VideoChromaFormat};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// A CUDA or NVDEC call failed.
+    Cuda(ffi::cuda::CUresult),
+    /// The bitstream parser rejected the data or could not be created.
+    Parser(ffi::cuda::CUresult),
+    /// The decoder configuration was rejected before touching CUDA.
+    InvalidConfig(&'static str),
+    /// NVDEC reported the picture as corrupted.
+    Corrupted { timestamp: i64 },
+    /// No frame arrived within the frame timeout.
+    Timeout,
+    /// The end of the stream was reached, no more frames will arrive.
+    Eos,
+    /// The GPU cannot decode the current sequence, no more frames will
+    /// arrive until `Decoder::flush`.
+    Unsupported(UnsupportedStream),
+    /// No decode session could be opened, with `sessions` already open in
+    /// this process, or no GPU of a `DecoderPool` has one left.
+    SessionsExhausted { sessions: usize },
+}
+
+/// Why the GPU cannot decode a sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnsupportedStream {
+    Codec {
+        codec: Codec,
+        chroma_format: VideoChromaFormat,
+        bit_depth: u8,
+    },
+    Resolution {
+        width: u32,
+        height: u32,
+        max_width: u32,
+        max_height: u32,
+    },
+    MacroblockCount {
+        macroblocks: u32,
+        max_macroblocks: u32,
+    },
+    /// None of the output surface formats can be produced for the stream.
+    OutputFormat,
+}
+
+impl DecodeError {
+    /// The underlying CUDA result, if there is one.
+    pub fn cuda_result(&self) -> Option<ffi::cuda::CUresult> {
+        match *self {
+            DecodeError::Cuda(res) | DecodeError::Parser(res) => Some(res),
+            _ => None,
+        }
+    }
+
+    /// Whether the decoder stopped for good, delivering nothing more until
+    /// `Decoder::flush`.
+    pub(crate) fn ends_stream(&self) -> bool {
+        matches!(
+            *self,
+            DecodeError::Unsupported(_) | DecodeError::SessionsExhausted { .. }
+        )
+    }
+}
+
+impl From<ffi::cuda::CUresult> for DecodeError {
+    fn from(res: ffi::cuda::CUresult) -> Self {
+        DecodeError::Cuda(res)
+    }
+}
+
+/// The driver's description of `res`.
+pub fn error_string(res: ffi::cuda::CUresult) -> String {
+    let mut s = std::ptr::null();
+
+    unsafe {
+        if ffi::cuda::cuGetErrorString(res, &mut s) != ffi::cuda::cudaError_enum_CUDA_SUCCESS
+            || s.is_null()
+        {
+            return "unknown error".to_string();
+        }
+
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Cuda(res) => write!(f, "CUDA error {}: {}", res, error_string(res)),
+            DecodeError::Parser(res) => {
+                write!(f, "bitstream parser error {}: {}", res, error_string(res))
+            }
+            DecodeError::InvalidConfig(reason) => {
+                write!(f, "invalid decoder configuration: {}", reason)
+            }
+            DecodeError::Corrupted { timestamp } => {
+                write!(f, "corrupted picture at timestamp {}", timestamp)
+            }
+            DecodeError::Timeout => f.write_str("timed out waiting for a frame"),
+            DecodeError::Eos => f.write_str("end of stream"),
+            DecodeError::Unsupported(reason) => write!(f, "unsupported stream: {}", reason),
+            DecodeError::SessionsExhausted { sessions } => write!(
+                f,
+                "no decode session available, {} open in this process",
+                sessions
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl fmt::Display for UnsupportedStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnsupportedStream::Codec {
+                codec,
+                chroma_format,
+                bit_depth,
+            } => write!(
+                f,
+                "{:?} {:?} at {} bit is not supported on this GPU",
+                codec, chroma_format, bit_depth
+            ),
+            UnsupportedStream::Resolution {
+                width,
+                height,
+                max_width,
+                max_height,
+            } => write!(
+                f,
+                "resolution {}x{} is greater than the maximum {}x{} of this GPU",
+                width, height, max_width, max_height
+            ),
+            UnsupportedStream::MacroblockCount {
+                macroblocks,
+                max_macroblocks,
+            } => write!(
+                f,
+                "{} macroblocks are more than the maximum {} of this GPU",
+                macroblocks, max_macroblocks
+            ),
+            UnsupportedStream::OutputFormat => f.write_str("no supported output format"),
+        }
+    }
+}
+
+/// Why `FramesIter::recv` returned no frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameRecvError {
+    /// No frame arrived within the frame timeout, more may still come.
+    Timeout,
+    /// The end of the stream was reached after `send_eos`.
+    Eos,
+    /// The decoder stopped delivering frames without an end of stream.
+    Disconnected,
+    /// The frame could not be mapped.
+    Decode(DecodeError),
+}
+
+impl fmt::Display for FrameRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameRecvError::Timeout => f.write_str("timed out waiting for a frame"),
+            FrameRecvError::Eos => f.write_str("end of stream"),
+            FrameRecvError::Disconnected => f.write_str("decoder stopped before the end of stream"),
+            FrameRecvError::Decode(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FrameRecvError {}
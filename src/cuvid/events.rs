@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use super::{DecodeError, VideoFormat};
+
+/// Hooks into what happens inside a decoder, for monitoring. Every method
+/// does nothing by default. They are called on the thread feeding the
+/// decoder, or receiving its frames for errors found while mapping, and
+/// should return quickly.
+pub trait DecoderEvents: Send + Sync {
+    /// A sequence header with a new format was parsed.
+    fn on_sequence_change(&self, _format: &VideoFormat) {}
+
+    /// A picture failed to decode or came out with errors, or the sequence
+    /// cannot be decoded at all.
+    fn on_decode_error(&self, _error: &DecodeError) {}
+
+    /// A picture waited this long for its surface, still held by a frame
+    /// that was not dropped yet.
+    fn on_surface_starvation(&self, _waited: Duration) {}
+
+    /// The parser reached the end of the stream.
+    fn on_eos(&self) {}
+}
@@ -0,0 +1,134 @@
+//! Codec headers carried by the container rather than in the stream, as in
+//! MP4 and Matroska.
+
+use super::{Codec, DecodeError};
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+const MALFORMED: DecodeError = DecodeError::InvalidConfig("malformed extradata");
+
+fn be16(data: &[u8], at: usize) -> Result<usize, DecodeError> {
+    match data.get(at..at + 2) {
+        Some(bytes) => Ok((usize::from(bytes[0]) << 8) | usize::from(bytes[1])),
+        None => Err(MALFORMED),
+    }
+}
+
+/// Reads `count` NAL units each prefixed with its 16 bit size, starting at
+/// `pos`, into `out` with start codes. Returns the position after them.
+fn nal_array(
+    data: &[u8],
+    mut pos: usize,
+    count: usize,
+    out: &mut Vec<u8>,
+) -> Result<usize, DecodeError> {
+    for _ in 0..count {
+        let size = be16(data, pos)?;
+        let nal = data.get(pos + 2..pos + 2 + size).ok_or(MALFORMED)?;
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(nal);
+        pos += 2 + size;
+    }
+
+    Ok(pos)
+}
+
+/// The parameter sets of `extradata` in Annex B form, and the size of the
+/// NAL unit length prefixes of the packets if they need rewriting.
+///
+/// An `avcC` or `hvcC` record means length prefixed packets. Anything else
+/// (Annex B parameter sets, AV1 sequence header OBUs, ...) is fed to the
+/// parser as is.
+pub(crate) fn parse(
+    codec: Codec,
+    extradata: &[u8],
+) -> Result<(Vec<u8>, Option<usize>), DecodeError> {
+    let annex_b = extradata.starts_with(&[0, 0, 1]) || extradata.starts_with(&START_CODE);
+    let mut out = Vec::with_capacity(extradata.len() + 16);
+
+    match codec {
+        Codec::H264 if !annex_b && extradata.first() == Some(&1) => {
+            if extradata.len() < 6 {
+                return Err(MALFORMED);
+            }
+            let length_size = usize::from(extradata[4] & 3) + 1;
+            let pos = nal_array(extradata, 6, usize::from(extradata[5] & 0x1f), &mut out)?;
+            let pps = *extradata.get(pos).ok_or(MALFORMED)?;
+            nal_array(extradata, pos + 1, usize::from(pps), &mut out)?;
+
+            Ok((out, Some(length_size)))
+        }
+        Codec::HEVC if !annex_b && extradata.first() == Some(&1) => {
+            if extradata.len() < 23 {
+                return Err(MALFORMED);
+            }
+            let length_size = usize::from(extradata[21] & 3) + 1;
+            let mut pos = 23;
+            for _ in 0..extradata[22] {
+                let count = be16(extradata, pos + 1)?;
+                pos = nal_array(extradata, pos + 3, count, &mut out)?;
+            }
+
+            Ok((out, Some(length_size)))
+        }
+        _ => {
+            out.extend_from_slice(extradata);
+            Ok((out, None))
+        }
+    }
+}
+
+/// Rewrites a packet of NAL units prefixed with their `length_size` bytes
+/// long size into Annex B.
+pub(crate) fn to_annex_b(
+    data: &[u8],
+    length_size: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), DecodeError> {
+    out.clear();
+    let mut pos = 0;
+    while pos < data.len() {
+        let prefix = data.get(pos..pos + length_size).ok_or(MALFORMED)?;
+        let size = prefix
+            .iter()
+            .fold(0usize, |size, &byte| (size << 8) | usize::from(byte));
+        let nal = data
+            .get(pos + length_size..pos + length_size + size)
+            .ok_or(MALFORMED)?;
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(nal);
+        pos += length_size + size;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avcc() {
+        let avcc = [
+            1, 0x64, 0, 0x1f, 0xff, 0xe1, 0, 3, 0x67, 0x64, 0x1f, 1, 0, 2, 0x68, 0xee,
+        ];
+        let (headers, length_size) = parse(Codec::H264, &avcc).unwrap();
+        assert_eq!(
+            headers,
+            [0, 0, 0, 1, 0x67, 0x64, 0x1f, 0, 0, 0, 1, 0x68, 0xee]
+        );
+        assert_eq!(length_size, Some(4));
+        assert_eq!(parse(Codec::H264, &avcc[..10]), Err(MALFORMED));
+
+        let annex_b = [0, 0, 0, 1, 0x67, 0x64];
+        assert_eq!(
+            parse(Codec::H264, &annex_b).unwrap(),
+            (annex_b.to_vec(), None)
+        );
+
+        let mut out = Vec::new();
+        to_annex_b(&[0, 0, 0, 2, 0x65, 0x88, 0, 0, 0, 1, 0x06], 4, &mut out).unwrap();
+        assert_eq!(out, [0, 0, 0, 1, 0x65, 0x88, 0, 0, 0, 1, 0x06]);
+        assert_eq!(to_annex_b(&[0, 0, 0, 9, 0x65], 4, &mut out), Err(MALFORMED));
+    }
+}
@@ -0,0 +1,148 @@
+use super::ffi;
+use super::{Codec, VideoChromaFormat};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn width(&self) -> u32 {
+        (self.right - self.left) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.bottom - self.top) as u32
+    }
+}
+
+/// Colour description of the stream, code points as in ITU-T H.273.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VideoSignalInfo {
+    pub video_format: u8,
+    pub full_range: bool,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+}
+
+impl Default for VideoSignalInfo {
+    /// Unspecified, what the bitstream implies when it has no description.
+    fn default() -> Self {
+        VideoSignalInfo {
+            video_format: 5,
+            full_range: false,
+            color_primaries: 2,
+            transfer_characteristics: 2,
+            matrix_coefficients: 2,
+        }
+    }
+}
+
+impl VideoSignalInfo {
+    /// Whether the transfer function is SMPTE ST 2084 (PQ, HDR10) or HLG.
+    pub fn is_hdr(&self) -> bool {
+        self.transfer_characteristics == 16 || self.transfer_characteristics == 18
+    }
+}
+
+impl From<&ffi::cuvid::CUVIDEOFORMAT> for VideoSignalInfo {
+    fn from(fmt: &ffi::cuvid::CUVIDEOFORMAT) -> Self {
+        let signal = &fmt.video_signal_description;
+
+        VideoSignalInfo {
+            video_format: signal.video_format(),
+            full_range: signal.video_full_range_flag() != 0,
+            color_primaries: signal.color_primaries,
+            transfer_characteristics: signal.transfer_characteristics,
+            matrix_coefficients: signal.matrix_coefficients,
+        }
+    }
+}
+
+/// Safe copy of the sequence information reported by the parser.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VideoFormat {
+    pub codec: Codec,
+    pub chroma_format: VideoChromaFormat,
+    pub frame_rate: (u32, u32),
+    pub progressive: bool,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    pub min_decode_surfaces: u8,
+    pub coded_size: (u32, u32),
+    pub display_area: Rect,
+    pub display_aspect_ratio: (i32, i32),
+    pub bitrate: u32,
+    pub video_signal: VideoSignalInfo,
+}
+
+impl From<&ffi::cuvid::CUVIDEOFORMAT> for VideoFormat {
+    fn from(fmt: &ffi::cuvid::CUVIDEOFORMAT) -> Self {
+        VideoFormat {
+            codec: fmt.codec.into(),
+            chroma_format: fmt.chroma_format.into(),
+            frame_rate: (fmt.frame_rate.numerator, fmt.frame_rate.denominator),
+            progressive: fmt.progressive_sequence != 0,
+            bit_depth_luma: fmt.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: fmt.bit_depth_chroma_minus8 + 8,
+            min_decode_surfaces: fmt.min_num_decode_surfaces,
+            coded_size: (fmt.coded_width, fmt.coded_height),
+            display_area: Rect {
+                left: fmt.display_area.left,
+                top: fmt.display_area.top,
+                right: fmt.display_area.right,
+                bottom: fmt.display_area.bottom,
+            },
+            display_aspect_ratio: (fmt.display_aspect_ratio.x, fmt.display_aspect_ratio.y),
+            bitrate: fmt.bitrate,
+            video_signal: fmt.into(),
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl VideoFormat {
+    /// Width to height ratio of the samples of a picture of `size` showing
+    /// the display area, (1, 1) if the stream does not tell.
+    pub fn sample_aspect_ratio(&self, size: (u32, u32)) -> (u32, u32) {
+        let (x, y) = self.display_aspect_ratio;
+        if x <= 0 || y <= 0 || size.0 == 0 || size.1 == 0 {
+            return (1, 1);
+        }
+
+        let width = x as u64 * u64::from(size.1);
+        let height = y as u64 * u64::from(size.0);
+        let divisor = gcd(width, height);
+
+        ((width / divisor) as u32, (height / divisor) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_aspect_ratio() {
+        let fmt: ffi::cuvid::CUVIDEOFORMAT = unsafe { std::mem::zeroed() };
+        let mut format = VideoFormat::from(&fmt);
+        format.display_aspect_ratio = (16, 9);
+        assert_eq!(format.sample_aspect_ratio((1920, 1080)), (1, 1));
+        // Anamorphic DVD.
+        assert_eq!(format.sample_aspect_ratio((720, 576)), (64, 45));
+
+        format.display_aspect_ratio = (0, 0);
+        assert_eq!(format.sample_aspect_ratio((720, 576)), (1, 1));
+    }
+}
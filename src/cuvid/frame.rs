@@ -0,0 +1,242 @@
+use super::super::cuda::context::CurrentContext;
+use super::super::cuda::mem::DeviceBuffer;
+use super::super::cuda::stream::CuStream;
+use super::super::{CudaResult, NppResult};
+use super::{ffi, CUdeviceptr, GpuFrame, Orientation, VideoSurfaceFormat};
+
+/// An NV12 picture in device memory owned by the caller rather than by the
+/// decoder surface pool.
+pub struct DeviceFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pitch: u32,
+    pub timestamp: i64,
+    /// Carried over from the decoded frame, to be applied or signalled
+    /// further down.
+    pub orientation: Orientation,
+    buffer: DeviceBuffer,
+}
+
+impl DeviceFrame {
+    /// Allocates an NV12 picture in the current context.
+    pub fn new(width: u32, height: u32) -> Result<Self, ffi::cuda::CUresult> {
+        let pitch = (width + 255) & !255;
+        let buffer = DeviceBuffer::new((pitch * (height + height.div_ceil(2))) as usize)?;
+
+        Ok(DeviceFrame {
+            width,
+            height,
+            pitch,
+            timestamp: 0,
+            orientation: Orientation::default(),
+            buffer,
+        })
+    }
+
+    /// An NV12 picture in memory of the caller, e.g. a slot of a ring
+    /// buffer, which is not freed with the frame.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must hold `pitch * (height + height.div_ceil(2))` bytes of
+    /// device memory that stay allocated for as long as the frame lives.
+    pub unsafe fn from_raw(ptr: CUdeviceptr, width: u32, height: u32, pitch: u32) -> Self {
+        let size = (pitch * (height + height.div_ceil(2))) as usize;
+
+        DeviceFrame {
+            width,
+            height,
+            pitch,
+            timestamp: 0,
+            orientation: Orientation::default(),
+            buffer: DeviceBuffer::from_raw(ptr, size),
+        }
+    }
+
+    pub fn ptr(&self) -> CUdeviceptr {
+        self.buffer.as_ptr()
+    }
+
+    /// Start of the interleaved chroma plane.
+    pub fn chroma_ptr(&self) -> CUdeviceptr {
+        self.ptr() + (self.pitch * self.height) as u64
+    }
+}
+
+/// Provides the frames that `FramesIter::try_next_copied` copies decoded
+/// pictures into, instead of allocating new ones. It is called with the
+/// decoder's context current.
+pub trait FrameAllocator: Send {
+    fn allocate(&mut self, width: u32, height: u32) -> Result<DeviceFrame, ffi::cuda::CUresult>;
+}
+
+impl<F> FrameAllocator for F
+where
+    F: FnMut(u32, u32) -> Result<DeviceFrame, ffi::cuda::CUresult> + Send,
+{
+    fn allocate(&mut self, width: u32, height: u32) -> Result<DeviceFrame, ffi::cuda::CUresult> {
+        self(width, height)
+    }
+}
+
+fn rect(size: (u32, u32)) -> ffi::npp::NppiRect {
+    ffi::npp::NppiRect {
+        x: 0,
+        y: 0,
+        width: size.0 as _,
+        height: size.1 as _,
+    }
+}
+
+fn size(size: (u32, u32)) -> ffi::npp::NppiSize {
+    ffi::npp::NppiSize {
+        width: size.0 as _,
+        height: size.1 as _,
+    }
+}
+
+/// Scales an NV12 picture: bilinear for luma, nearest neighbour for the
+/// interleaved chroma plane so that U and V samples are never mixed.
+pub fn scale_nv12(
+    src: CUdeviceptr,
+    src_pitch: u32,
+    src_size: (u32, u32),
+    dst: CUdeviceptr,
+    dst_pitch: u32,
+    dst_size: (u32, u32),
+    stream: Option<&CuStream>,
+) -> Result<(), ffi::npp::NppStatus> {
+    scale_planes(
+        (src, src + (src_pitch * src_size.1) as u64),
+        src_pitch,
+        src_size,
+        (dst, dst + (dst_pitch * dst_size.1) as u64),
+        dst_pitch,
+        dst_size,
+        stream,
+    )
+}
+
+/// `scale_nv12` with the luma and chroma planes given separately, for
+/// pictures whose chroma plane does not directly follow the luma one.
+pub(crate) fn scale_planes(
+    src: (CUdeviceptr, CUdeviceptr),
+    src_pitch: u32,
+    src_size: (u32, u32),
+    dst: (CUdeviceptr, CUdeviceptr),
+    dst_pitch: u32,
+    dst_size: (u32, u32),
+    stream: Option<&CuStream>,
+) -> Result<(), ffi::npp::NppStatus> {
+    let stream_ctx = super::super::npp_stream_context(stream)?;
+    let src_chroma = (src_size.0 / 2, src_size.1.div_ceil(2));
+    let dst_chroma = (dst_size.0 / 2, dst_size.1.div_ceil(2));
+
+    unsafe {
+        ffi::npp::nppiResize_8u_C1R_Ctx(
+            src.0 as *const ffi::npp::Npp8u,
+            src_pitch as _,
+            size(src_size),
+            rect(src_size),
+            dst.0 as *mut ffi::npp::Npp8u,
+            dst_pitch as _,
+            size(dst_size),
+            rect(dst_size),
+            ffi::npp::NppiInterpolationMode_NPPI_INTER_LINEAR as _,
+            stream_ctx,
+        )
+        .err()?;
+
+        ffi::npp::nppiResize_16u_C1R_Ctx(
+            src.1 as *const ffi::npp::Npp16u,
+            src_pitch as _,
+            size(src_chroma),
+            rect(src_chroma),
+            dst.1 as *mut ffi::npp::Npp16u,
+            dst_pitch as _,
+            size(dst_chroma),
+            rect(dst_chroma),
+            ffi::npp::NppiInterpolationMode_NPPI_INTER_NN as _,
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    Ok(())
+}
+
+impl GpuFrame {
+    /// Produces an additional, differently sized copy of the mapped picture.
+    ///
+    /// NVDEC only scales to the size chosen when the decoder is created, so
+    /// further sizes (e.g. a small copy for inference next to the full
+    /// resolution one) are derived from the single mapping with NPP and the
+    /// decoder surface is released as usual when the frame is dropped.
+    /// `current` must be the context the frame is mapped in.
+    pub fn scale_into(
+        &self,
+        dst: &mut DeviceFrame,
+        current: &CurrentContext,
+        stream: Option<&CuStream>,
+    ) -> Result<(), ffi::npp::NppStatus> {
+        if !self.is_current(current) {
+            return Err(ffi::npp::NppStatus_NPP_CONTEXT_MATCH_ERR);
+        }
+        if self.format != VideoSurfaceFormat::NV12 {
+            return Err(ffi::npp::NppStatus_NPP_NOT_SUPPORTED_MODE_ERROR);
+        }
+
+        scale_nv12(
+            self.ptr,
+            self.pitch,
+            (self.width, self.height),
+            dst.ptr(),
+            dst.pitch,
+            (dst.width, dst.height),
+            stream,
+        )?;
+        dst.timestamp = self.timestamp;
+        dst.orientation = self.orientation;
+
+        Ok(())
+    }
+
+    /// Copies the mapped NV12 picture into `dst`, which must have the same
+    /// size, so that it outlives the decoder surface. `current` must be the
+    /// context the frame is mapped in.
+    pub fn copy_into(
+        &self,
+        dst: &mut DeviceFrame,
+        current: &CurrentContext,
+        stream: Option<&CuStream>,
+    ) -> Result<(), ffi::cuda::CUresult> {
+        if !self.is_current(current) {
+            return Err(ffi::cuda::cudaError_enum_CUDA_ERROR_INVALID_CONTEXT);
+        }
+        if self.format != VideoSurfaceFormat::NV12
+            || (self.width, self.height) != (dst.width, dst.height)
+        {
+            return Err(ffi::cuda::cudaError_enum_CUDA_ERROR_INVALID_VALUE);
+        }
+        let stream = stream.map(|s| s.stream).unwrap_or(std::ptr::null_mut());
+
+        let mut copy: ffi::cuda::CUDA_MEMCPY2D = unsafe { std::mem::zeroed() };
+        copy.srcMemoryType = ffi::cuda::CUmemorytype_enum_CU_MEMORYTYPE_DEVICE;
+        copy.srcDevice = self.ptr;
+        copy.srcPitch = self.pitch as _;
+        copy.dstMemoryType = ffi::cuda::CUmemorytype_enum_CU_MEMORYTYPE_DEVICE;
+        copy.dstDevice = dst.ptr();
+        copy.dstPitch = dst.pitch as _;
+        copy.WidthInBytes = self.width as _;
+        copy.Height = (self.height + self.height.div_ceil(2)) as _;
+
+        unsafe {
+            ffi::cuda::cuMemcpy2DAsync_v2(&copy, stream).err()?;
+            ffi::cuda::cuStreamSynchronize(stream).err()?;
+        }
+        dst.timestamp = self.timestamp;
+        dst.orientation = self.orientation;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! Keyframe index built with the parser-only mode, for fast seeking.
+
+use super::parser::ParserEvent;
+use super::{ffi, Codec, Parser, VideoFormat};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Keyframe {
+    /// Byte offset of the access unit in the indexed stream.
+    pub offset: u64,
+    pub timestamp: i64,
+    /// Position of the picture in decode order.
+    pub picture: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FormatChange {
+    /// Byte offset of the access unit carrying the new sequence header.
+    pub offset: u64,
+    /// Decode order position of the first picture using the new format.
+    pub picture: u64,
+    pub format: VideoFormat,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    pub keyframes: Vec<Keyframe>,
+    pub format_changes: Vec<FormatChange>,
+    pub pictures: u64,
+}
+
+impl Index {
+    /// The last keyframe at or before `timestamp`, where decoding has to
+    /// start to present it.
+    pub fn seek(&self, timestamp: i64) -> Option<&Keyframe> {
+        self.keyframes
+            .iter()
+            .rev()
+            .find(|k| k.timestamp <= timestamp)
+    }
+
+    /// The format in effect for the picture at decode order `picture`.
+    pub fn format_at(&self, picture: u64) -> Option<&VideoFormat> {
+        self.format_changes
+            .iter()
+            .rev()
+            .find(|c| c.picture <= picture)
+            .map(|c| &c.format)
+    }
+
+    /// Number of pictures from each keyframe to the next, in decode order.
+    /// Pictures before the first keyframe are not counted.
+    pub fn gop_sizes(&self) -> Vec<u64> {
+        self.keyframes
+            .windows(2)
+            .map(|pair| pair[1].picture - pair[0].picture)
+            .chain(self.keyframes.last().map(|k| self.pictures - k.picture))
+            .collect()
+    }
+
+    fn record(&mut self, event: ParserEvent, offset: u64) {
+        match event {
+            ParserEvent::Sequence(format) => {
+                let changed = self
+                    .format_changes
+                    .last()
+                    .map(|c| {
+                        c.format.coded_size != format.coded_size
+                            || c.format.display_area != format.display_area
+                            || c.format.chroma_format != format.chroma_format
+                            || c.format.bit_depth_luma != format.bit_depth_luma
+                    })
+                    .unwrap_or(true);
+
+                if changed {
+                    self.format_changes.push(FormatChange {
+                        offset,
+                        picture: self.pictures,
+                        format,
+                    });
+                }
+            }
+            ParserEvent::Picture(info) => {
+                if info.keyframe {
+                    self.keyframes.push(Keyframe {
+                        offset: info.offset,
+                        timestamp: info.timestamp,
+                        picture: self.pictures,
+                    });
+                }
+                self.pictures += 1;
+            }
+            ParserEvent::Display(_) => {}
+        }
+    }
+}
+
+/// Scans a bitstream fed one access unit at a time and builds its `Index`.
+pub struct Indexer {
+    parser: Parser,
+    index: Index,
+}
+
+impl Indexer {
+    pub fn new(codec: Codec) -> Result<Self, ffi::cuda::CUresult> {
+        Ok(Indexer {
+            parser: Parser::new(codec)?,
+            index: Index::default(),
+        })
+    }
+
+    pub fn push(&mut self, access_unit: &[u8], timestamp: i64) -> Result<(), ffi::cuda::CUresult> {
+        let offset = self.parser.position();
+        let index = &mut self.index;
+
+        for event in self.parser.parse_access_unit(access_unit, timestamp)? {
+            index.record(event, offset);
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Index, ffi::cuda::CUresult> {
+        let offset = self.parser.position();
+        let index = &mut self.index;
+
+        for event in self.parser.finish()? {
+            index.record(event, offset);
+        }
+
+        Ok(self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::PictureInfo;
+    use super::super::{PictureType, Rect, VideoChromaFormat};
+    use super::*;
+
+    fn format(width: u32, height: u32) -> VideoFormat {
+        VideoFormat {
+            codec: Codec::H264,
+            chroma_format: VideoChromaFormat::YUV420,
+            frame_rate: (30, 1),
+            progressive: true,
+            bit_depth_luma: 8,
+            bit_depth_chroma: 8,
+            min_decode_surfaces: 4,
+            coded_size: (width, height),
+            display_area: Rect {
+                left: 0,
+                top: 0,
+                right: width as i32,
+                bottom: height as i32,
+            },
+            display_aspect_ratio: (16, 9),
+            bitrate: 0,
+            video_signal: Default::default(),
+        }
+    }
+
+    fn picture(keyframe: bool, offset: u64, timestamp: i64) -> ParserEvent {
+        ParserEvent::Picture(PictureInfo {
+            index: 0,
+            picture_type: if keyframe {
+                PictureType::I
+            } else {
+                PictureType::P
+            },
+            keyframe,
+            reference: true,
+            field: false,
+            poc: None,
+            size: 0,
+            offset,
+            timestamp,
+        })
+    }
+
+    #[test]
+    fn keyframes_and_format_changes() {
+        let mut index = Index::default();
+
+        index.record(ParserEvent::Sequence(format(1920, 1080)), 0);
+        index.record(picture(true, 0, 0), 0);
+        index.record(picture(false, 100, 10), 100);
+        index.record(ParserEvent::Sequence(format(1920, 1080)), 200);
+        index.record(picture(true, 200, 20), 200);
+        index.record(ParserEvent::Sequence(format(1280, 720)), 300);
+        index.record(picture(true, 300, 30), 300);
+        index.record(picture(false, 400, 40), 400);
+
+        assert_eq!(index.pictures, 5);
+        assert_eq!(index.keyframes.len(), 3);
+        assert_eq!(index.format_changes.len(), 2);
+        assert_eq!(index.format_changes[1].picture, 3);
+
+        assert_eq!(index.seek(15).map(|k| k.offset), Some(0));
+        assert_eq!(index.seek(45).map(|k| k.offset), Some(300));
+        assert_eq!(index.seek(-1), None);
+        assert_eq!(index.format_at(4).map(|f| f.coded_size), Some((1280, 720)));
+        assert_eq!(index.gop_sizes(), [2, 1, 2]);
+        assert!(Index::default().gop_sizes().is_empty());
+    }
+}
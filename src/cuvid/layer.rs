@@ -0,0 +1,62 @@
+use super::ffi;
+use super::Codec;
+
+/// A set of spatial and temporal layers of a scalable AV1 stream that can be
+/// decoded on its own, as listed in the sequence header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OperatingPoint {
+    /// The `operating_point_idc` of the sequence header, 0 when the stream
+    /// is not scalable.
+    pub idc: u16,
+}
+
+impl OperatingPoint {
+    /// Bit mask of the spatial layers included, bit 0 for the base layer.
+    pub fn spatial_layers(self) -> u8 {
+        ((self.idc >> 8) & 0xf) as u8
+    }
+
+    /// Bit mask of the temporal layers included, bit 0 for the base layer.
+    pub fn temporal_layers(self) -> u8 {
+        (self.idc & 0xff) as u8
+    }
+}
+
+/// The operating points offered by the stream, empty for other codecs.
+pub(crate) fn operating_points(info: &ffi::cuvid::CUVIDOPERATINGPOINTINFO) -> Vec<OperatingPoint> {
+    if Codec::from(info.codec) != Codec::AV1 {
+        return Vec::new();
+    }
+
+    let av1 = unsafe { &info.__bindgen_anon_1.av1 };
+    let count = (av1.operating_points_cnt as usize).min(av1.operating_points_idc.len());
+    av1.operating_points_idc[..count]
+        .iter()
+        .map(|&idc| OperatingPoint { idc })
+        .collect()
+}
+
+/// The value handed back to the parser: the operating point in bits 0-9
+/// and whether to output all its layers in bit 10. An index the stream
+/// lacks falls back to the first operating point.
+pub(crate) fn select(available: usize, index: usize, all_layers: bool) -> i32 {
+    let index = if index < available { index } else { 0 };
+
+    index as i32 | (all_layers as i32) << 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_and_selection() {
+        let point = OperatingPoint { idc: 0x0303 };
+        assert_eq!(point.spatial_layers(), 0b11);
+        assert_eq!(point.temporal_layers(), 0b11);
+
+        assert_eq!(select(3, 2, false), 2);
+        assert_eq!(select(3, 1, true), 1 | 1 << 10);
+        assert_eq!(select(1, 2, false), 0);
+    }
+}
@@ -0,0 +1,122 @@
+/// Clockwise rotation turning the stored picture upright.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Rotate180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// The rotation closest to `degrees` clockwise.
+    pub fn from_degrees(degrees: i32) -> Self {
+        match (degrees.rem_euclid(360) + 45) / 90 % 4 {
+            0 => Rotation::None,
+            1 => Rotation::Clockwise90,
+            2 => Rotation::Rotate180,
+            _ => Rotation::Clockwise270,
+        }
+    }
+
+    pub fn degrees(self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Rotate180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+}
+
+/// Display orientation of a picture, from the container (e.g. the MP4
+/// display matrix of phone footage) or the display orientation SEI. The
+/// picture is mirrored left to right first, if at all, then rotated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub mirror: bool,
+}
+
+impl Orientation {
+    pub fn new(rotation: Rotation, mirror: bool) -> Self {
+        Orientation { rotation, mirror }
+    }
+
+    pub fn is_upright(&self) -> bool {
+        *self == Orientation::default()
+    }
+
+    /// Whether width and height swap when the orientation is applied.
+    pub fn transposes(&self) -> bool {
+        match self.rotation {
+            Rotation::Clockwise90 | Rotation::Clockwise270 => true,
+            Rotation::None | Rotation::Rotate180 => false,
+        }
+    }
+
+    /// The orientation as a transposition, if any, followed by a mirroring
+    /// left to right and one top to bottom.
+    pub(crate) fn steps(&self) -> (bool, bool, bool) {
+        match (self.rotation, self.mirror) {
+            (Rotation::None, false) => (false, false, false),
+            (Rotation::None, true) => (false, true, false),
+            (Rotation::Rotate180, false) => (false, true, true),
+            (Rotation::Rotate180, true) => (false, false, true),
+            (Rotation::Clockwise90, false) => (true, true, false),
+            (Rotation::Clockwise90, true) => (true, true, true),
+            (Rotation::Clockwise270, false) => (true, false, true),
+            (Rotation::Clockwise270, true) => (true, false, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_match_rotations() {
+        // Applies the steps to pixel (x, y) of a w x h picture.
+        fn apply(orientation: Orientation, (x, y): (i32, i32), (w, h): (i32, i32)) -> (i32, i32) {
+            let (transpose, left_right, top_bottom) = orientation.steps();
+            let ((mut x, mut y), (w, h)) = if transpose {
+                ((y, x), (h, w))
+            } else {
+                ((x, y), (w, h))
+            };
+            if left_right {
+                x = w - 1 - x;
+            }
+            if top_bottom {
+                y = h - 1 - y;
+            }
+            (x, y)
+        }
+
+        let size = (4, 2);
+        for &(rotation, turns) in &[
+            (Rotation::None, 0),
+            (Rotation::Clockwise90, 1),
+            (Rotation::Rotate180, 2),
+            (Rotation::Clockwise270, 3),
+        ] {
+            for &mirror in &[false, true] {
+                let mut expected = (if mirror { 3 } else { 0 }, 1);
+                let mut bounds = size;
+                for _ in 0..turns {
+                    expected = (bounds.1 - 1 - expected.1, expected.0);
+                    bounds = (bounds.1, bounds.0);
+                }
+
+                let orientation = Orientation::new(rotation, mirror);
+                assert_eq!(apply(orientation, (0, 1), size), expected);
+                assert_eq!(orientation.transposes(), turns % 2 == 1);
+            }
+        }
+
+        assert_eq!(Rotation::from_degrees(-90), Rotation::Clockwise270);
+        assert_eq!(Rotation::from_degrees(181), Rotation::Rotate180);
+        assert_eq!(Rotation::from_degrees(350), Rotation::None);
+    }
+}
@@ -0,0 +1,45 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use super::ffi;
+
+/// Flags of a queued packet, combined with `|`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PacketFlags(ffi::cuvid::CUvideopacketflags);
+
+impl PacketFlags {
+    /// The packet does not continue the previous one, e.g. after packet
+    /// loss or when splicing streams. The parser drops its partial state.
+    pub const DISCONTINUITY: PacketFlags =
+        PacketFlags(ffi::cuvid::CUvideopacketflags_CUVID_PKT_DISCONTINUITY);
+    /// The packet completes a picture, which is then decoded right away.
+    pub const END_OF_PICTURE: PacketFlags =
+        PacketFlags(ffi::cuvid::CUvideopacketflags_CUVID_PKT_ENDOFPICTURE);
+
+    pub fn empty() -> Self {
+        PacketFlags(0)
+    }
+
+    pub fn contains(self, other: PacketFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for PacketFlags {
+    type Output = PacketFlags;
+
+    fn bitor(self, other: PacketFlags) -> PacketFlags {
+        PacketFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for PacketFlags {
+    fn bitor_assign(&mut self, other: PacketFlags) {
+        self.0 |= other.0;
+    }
+}
+
+impl From<PacketFlags> for ffi::cuvid::CUvideopacketflags {
+    fn from(flags: PacketFlags) -> Self {
+        flags.0
+    }
+}
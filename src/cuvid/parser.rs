@@ -0,0 +1,215 @@
+//! Parser-only mode: runs the CUVID bitstream parser without creating a
+//! decoder, so no NVDEC session or CUDA context is needed.
+
+use super::picture;
+use super::{ffi, Codec, PictureType, VideoFormat};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PictureInfo {
+    /// Decode surface index assigned by the parser.
+    pub index: i32,
+    pub picture_type: PictureType,
+    pub keyframe: bool,
+    pub reference: bool,
+    pub field: bool,
+    pub poc: Option<i32>,
+    /// Size of the picture's slice data.
+    pub size: u32,
+    /// Byte offset of the packet during which the picture was completed.
+    /// Exact when every packet is a whole access unit.
+    pub offset: u64,
+    /// Timestamp of the packet during which the picture was completed.
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DisplayInfo {
+    pub index: i32,
+    pub timestamp: i64,
+    pub progressive: bool,
+    pub top_field_first: bool,
+    pub repeat_first_field: i32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParserEvent {
+    /// A new or changed sequence header.
+    Sequence(VideoFormat),
+    /// A picture in decode order.
+    Picture(PictureInfo),
+    /// A picture in display order.
+    Display(DisplayInfo),
+}
+
+pub struct Parser {
+    inner: Box<Inner>,
+}
+
+unsafe impl Send for Parser {}
+
+struct Inner {
+    parser: ffi::cuvid::CUvideoparser,
+    codec: Codec,
+    events: Vec<ParserEvent>,
+    position: u64,
+    packet_offset: u64,
+    packet_timestamp: i64,
+}
+
+impl Parser {
+    pub fn new(codec: Codec) -> Result<Self, ffi::cuda::CUresult> {
+        let mut inner = Box::new(Inner {
+            parser: std::ptr::null_mut(),
+            codec,
+            events: Vec::new(),
+            position: 0,
+            packet_offset: 0,
+            packet_timestamp: 0,
+        });
+
+        let mut params: ffi::cuvid::CUVIDPARSERPARAMS = unsafe { std::mem::zeroed() };
+        params.CodecType = codec.into();
+        params.ulMaxNumDecodeSurfaces = 1;
+        params.ulClockRate = 10000000;
+        params.pfnSequenceCallback = Some(sequence_proc);
+        params.pfnDecodePicture = Some(decode_proc);
+        params.pfnDisplayPicture = Some(display_proc);
+        params.pUserData = (&mut *inner as *mut Inner) as *mut std::os::raw::c_void;
+
+        let mut parser: ffi::cuvid::CUvideoparser = std::ptr::null_mut();
+        unsafe {
+            let res = ffi::cuvid::cuvidCreateVideoParser(&mut parser, &mut params);
+            wrap!(res, res)?;
+        }
+        inner.parser = parser;
+
+        Ok(Parser { inner })
+    }
+
+    /// Feeds an arbitrary chunk of the bitstream.
+    pub fn parse(
+        &mut self,
+        data: &[u8],
+        timestamp: i64,
+    ) -> Result<std::vec::Drain<'_, ParserEvent>, ffi::cuda::CUresult> {
+        self.submit(
+            data,
+            timestamp,
+            ffi::cuvid::CUvideopacketflags_CUVID_PKT_TIMESTAMP,
+        )
+    }
+
+    /// Feeds exactly one access unit, so its picture is reported right away.
+    pub fn parse_access_unit(
+        &mut self,
+        data: &[u8],
+        timestamp: i64,
+    ) -> Result<std::vec::Drain<'_, ParserEvent>, ffi::cuda::CUresult> {
+        self.submit(
+            data,
+            timestamp,
+            ffi::cuvid::CUvideopacketflags_CUVID_PKT_TIMESTAMP
+                | ffi::cuvid::CUvideopacketflags_CUVID_PKT_ENDOFPICTURE,
+        )
+    }
+
+    /// Signals the end of the stream, flushing the pictures still buffered.
+    pub fn finish(&mut self) -> Result<std::vec::Drain<'_, ParserEvent>, ffi::cuda::CUresult> {
+        self.submit(&[], 0, ffi::cuvid::CUvideopacketflags_CUVID_PKT_ENDOFSTREAM)
+    }
+
+    /// Number of bytes fed so far.
+    pub fn position(&self) -> u64 {
+        self.inner.position
+    }
+
+    fn submit(
+        &mut self,
+        data: &[u8],
+        timestamp: i64,
+        flags: ffi::cuvid::CUvideopacketflags,
+    ) -> Result<std::vec::Drain<'_, ParserEvent>, ffi::cuda::CUresult> {
+        self.inner.packet_offset = self.inner.position;
+        self.inner.packet_timestamp = timestamp;
+        self.inner.position += data.len() as u64;
+
+        let mut packet = ffi::cuvid::CUVIDSOURCEDATAPACKET {
+            flags: flags as _,
+            payload_size: data.len() as _,
+            payload: data.as_ptr(),
+            timestamp,
+        };
+
+        unsafe {
+            let res = ffi::cuvid::cuvidParseVideoData(self.inner.parser, &mut packet);
+            wrap!(res, res)?;
+        }
+
+        Ok(self.inner.events.drain(..))
+    }
+}
+
+impl Drop for Parser {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::cuvid::cuvidDestroyVideoParser(self.inner.parser);
+        }
+    }
+}
+
+unsafe extern "C" fn sequence_proc(
+    user_data: *mut std::os::raw::c_void,
+    video_format: *mut ffi::cuvid::CUVIDEOFORMAT,
+) -> i32 {
+    let inner = &mut *(user_data as *mut Inner);
+    let fmt = &*video_format;
+
+    inner.codec = fmt.codec.into();
+    inner.events.push(ParserEvent::Sequence(fmt.into()));
+
+    fmt.min_num_decode_surfaces.max(1) as _
+}
+
+unsafe extern "C" fn decode_proc(
+    user_data: *mut std::os::raw::c_void,
+    pic_params: *mut ffi::cuvid::CUVIDPICPARAMS,
+) -> i32 {
+    let inner = &mut *(user_data as *mut Inner);
+    let params = &*pic_params;
+
+    inner.events.push(ParserEvent::Picture(PictureInfo {
+        index: params.CurrPicIdx,
+        picture_type: PictureType::from_params(params),
+        keyframe: picture::is_keyframe(inner.codec, params),
+        reference: params.ref_pic_flag != 0,
+        field: params.field_pic_flag != 0,
+        poc: picture::picture_order_count(inner.codec, params),
+        size: params.nBitstreamDataLen,
+        offset: inner.packet_offset,
+        timestamp: inner.packet_timestamp,
+    }));
+
+    1
+}
+
+unsafe extern "C" fn display_proc(
+    user_data: *mut std::os::raw::c_void,
+    display_info: *mut ffi::cuvid::CUVIDPARSERDISPINFO,
+) -> i32 {
+    if display_info.is_null() {
+        return 1;
+    }
+
+    let inner = &mut *(user_data as *mut Inner);
+    let info = &*display_info;
+
+    inner.events.push(ParserEvent::Display(DisplayInfo {
+        index: info.picture_index,
+        timestamp: info.timestamp,
+        progressive: info.progressive_frame != 0,
+        top_field_first: info.top_field_first != 0,
+        repeat_first_field: info.repeat_first_field,
+    }));
+
+    1
+}
@@ -0,0 +1,81 @@
+use super::ffi;
+use super::Codec;
+
+/// Coding type of a picture as far as NVDEC reports it.
+///
+/// The parser only exposes intra and reference flags, so inter pictures used
+/// as reference are reported as `P` and the others as `B`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PictureType {
+    I,
+    P,
+    B,
+}
+
+impl PictureType {
+    pub(crate) fn from_params(params: &ffi::cuvid::CUVIDPICPARAMS) -> Self {
+        if params.intra_pic_flag != 0 {
+            PictureType::I
+        } else if params.ref_pic_flag != 0 {
+            PictureType::P
+        } else {
+            PictureType::B
+        }
+    }
+}
+
+/// Pictures left undecoded to keep up, see `Decoder::set_frame_skip`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameSkip {
+    /// Decode every picture, the default.
+    Never,
+    /// Skip the pictures no other one refers to, e.g. most B-frames.
+    NonReference,
+    /// Skip non-reference pictures while more than this many decoded
+    /// pictures wait to be received.
+    NonReferenceWhenBehind(usize),
+}
+
+impl FrameSkip {
+    /// Whether the picture is skipped with `backlog` pictures waiting.
+    pub(crate) fn skips(self, params: &ffi::cuvid::CUVIDPICPARAMS, backlog: usize) -> bool {
+        let behind = match self {
+            FrameSkip::Never => return false,
+            FrameSkip::NonReference => true,
+            FrameSkip::NonReferenceWhenBehind(pictures) => backlog > pictures,
+        };
+
+        behind && params.ref_pic_flag == 0 && params.intra_pic_flag == 0
+    }
+}
+
+/// Whether the picture is a random access point for the given codec.
+pub(crate) fn is_keyframe(codec: Codec, params: &ffi::cuvid::CUVIDPICPARAMS) -> bool {
+    match codec {
+        Codec::HEVC => unsafe { params.CodecSpecific.hevc.IrapPicFlag != 0 },
+        _ => params.intra_pic_flag != 0,
+    }
+}
+
+/// Picture order count of the picture, for the codecs carrying one.
+pub(crate) fn picture_order_count(
+    codec: Codec,
+    params: &ffi::cuvid::CUVIDPICPARAMS,
+) -> Option<i32> {
+    unsafe {
+        match codec {
+            Codec::H264 | Codec::H264Svc | Codec::H264Mvc => {
+                let poc = params.CodecSpecific.h264.CurrFieldOrderCnt;
+                Some(
+                    if params.field_pic_flag != 0 && params.bottom_field_flag != 0 {
+                        poc[1]
+                    } else {
+                        poc[0]
+                    },
+                )
+            }
+            Codec::HEVC => Some(params.CodecSpecific.hevc.CurrPicOrderCntVal),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Spreading decoders over the GPUs of the machine.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::{ffi, DecodeError, Decoder, DecoderBuilder};
+use cuda::device::{self, CuDevice};
+use CudaResult;
+
+/// Load of one GPU when a decoder is placed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GpuStats {
+    pub gpu_id: usize,
+    /// Decoders of the pool currently open on the GPU.
+    pub sessions: usize,
+    /// Device memory free for the whole process, including what other
+    /// processes use.
+    pub free_memory: u64,
+    pub total_memory: u64,
+}
+
+/// Chooses the GPU of a new decoder among those with sessions left, by
+/// index into `gpus`, or none to refuse it.
+pub trait Placement: Send + Sync {
+    fn place(&self, gpus: &[GpuStats]) -> Option<usize>;
+}
+
+impl<F> Placement for F
+where
+    F: Fn(&[GpuStats]) -> Option<usize> + Send + Sync,
+{
+    fn place(&self, gpus: &[GpuStats]) -> Option<usize> {
+        self(gpus)
+    }
+}
+
+/// The GPU with the fewest sessions, the most free memory on ties.
+pub struct LeastSessions;
+
+impl Placement for LeastSessions {
+    fn place(&self, gpus: &[GpuStats]) -> Option<usize> {
+        (0..gpus.len()).min_by_key(|&i| (gpus[i].sessions, u64::MAX - gpus[i].free_memory))
+    }
+}
+
+/// The GPU with the most free memory.
+pub struct MostFreeMemory;
+
+impl Placement for MostFreeMemory {
+    fn place(&self, gpus: &[GpuStats]) -> Option<usize> {
+        (0..gpus.len()).max_by_key(|&i| gpus[i].free_memory)
+    }
+}
+
+struct Gpu {
+    id: usize,
+    device: CuDevice,
+    /// The primary context, only retained to query the free memory.
+    context: ffi::cuda::CUcontext,
+    total_memory: u64,
+    sessions: Arc<AtomicUsize>,
+}
+
+/// Creates decoders on the GPUs picked by a `Placement`, `LeastSessions`
+/// by default, and keeps count of them.
+pub struct DecoderPool {
+    gpus: Vec<Gpu>,
+    placement: Box<dyn Placement>,
+    max_sessions: usize,
+}
+
+unsafe impl Send for DecoderPool {}
+unsafe impl Sync for DecoderPool {}
+
+impl DecoderPool {
+    /// A pool over every GPU of the machine.
+    pub fn new() -> Result<Self, DecodeError> {
+        ::init();
+        let count = device::get_count()?;
+
+        Self::with_gpus((0..count as usize).collect::<Vec<_>>())
+    }
+
+    pub fn with_gpus<I: IntoIterator<Item = usize>>(gpu_ids: I) -> Result<Self, DecodeError> {
+        ::init();
+        let mut gpus = Vec::new();
+        for id in gpu_ids {
+            let device = CuDevice::new(id as _)?;
+            let total_memory = device.get_total_mem()?;
+            let mut context = std::ptr::null_mut();
+            unsafe {
+                ffi::cuda::cuDevicePrimaryCtxRetain(&mut context, device.device).err()?;
+            }
+
+            gpus.push(Gpu {
+                id,
+                device,
+                context,
+                total_memory,
+                sessions: Arc::new(AtomicUsize::new(0)),
+            });
+        }
+
+        Ok(DecoderPool {
+            gpus,
+            placement: Box::new(LeastSessions),
+            max_sessions: usize::MAX,
+        })
+    }
+
+    pub fn placement<P: Placement + 'static>(mut self, placement: P) -> Self {
+        self.placement = Box::new(placement);
+        self
+    }
+
+    /// Refuse new decoders on GPUs already running `max_sessions`.
+    pub fn max_sessions_per_gpu(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    pub fn stats(&self) -> Vec<GpuStats> {
+        self.gpus.iter().map(|gpu| self.gpu_stats(gpu)).collect()
+    }
+
+    fn gpu_stats(&self, gpu: &Gpu) -> GpuStats {
+        let mut free = 0;
+        let mut total = 0;
+        unsafe {
+            if ffi::cuda::cuCtxPushCurrent_v2(gpu.context).ok() {
+                if !ffi::cuda::cuMemGetInfo_v2(&mut free, &mut total).ok() {
+                    tracing::warn!("Failed to query the memory of GPU {}.", gpu.id);
+                }
+                ffi::cuda::cuCtxPopCurrent_v2(std::ptr::null_mut());
+            }
+        }
+
+        GpuStats {
+            gpu_id: gpu.id,
+            sessions: gpu.sessions.load(Ordering::SeqCst),
+            free_memory: free as u64,
+            total_memory: gpu.total_memory,
+        }
+    }
+
+    fn exhausted(&self) -> DecodeError {
+        DecodeError::SessionsExhausted {
+            sessions: self
+                .gpus
+                .iter()
+                .map(|gpu| gpu.sessions.load(Ordering::SeqCst))
+                .sum(),
+        }
+    }
+
+    /// Builds a decoder from `builder` on the GPU chosen by the placement.
+    /// Any context or GPU set on the builder is replaced.
+    pub fn create(&self, builder: DecoderBuilder) -> Result<PooledDecoder, DecodeError> {
+        let candidates: Vec<(&Gpu, GpuStats)> = self
+            .gpus
+            .iter()
+            .map(|gpu| (gpu, self.gpu_stats(gpu)))
+            .filter(|(_, stats)| stats.sessions < self.max_sessions)
+            .collect();
+        let stats: Vec<GpuStats> = candidates.iter().map(|(_, stats)| *stats).collect();
+
+        if candidates.is_empty() && !self.gpus.is_empty() {
+            return Err(self.exhausted());
+        }
+        let gpu = match self.placement.place(&stats) {
+            Some(index) if index < candidates.len() => candidates[index].0,
+            _ => {
+                return Err(DecodeError::InvalidConfig(
+                    "no GPU left to place the decoder on",
+                ))
+            }
+        };
+
+        let session = Session(Arc::clone(&gpu.sessions));
+        // Another thread may have taken the last session meanwhile.
+        if gpu.sessions.fetch_add(1, Ordering::SeqCst) >= self.max_sessions {
+            return Err(self.exhausted());
+        }
+        let decoder = builder.unset_context().gpu(gpu.id).build()?;
+        tracing::debug!("Placed a decoder on GPU {}.", gpu.id);
+
+        Ok(PooledDecoder {
+            decoder,
+            gpu_id: gpu.id,
+            _session: session,
+        })
+    }
+}
+
+impl Drop for DecoderPool {
+    fn drop(&mut self) {
+        for gpu in &self.gpus {
+            unsafe {
+                ffi::cuda::cuDevicePrimaryCtxRelease_v2(gpu.device.device);
+            }
+        }
+    }
+}
+
+struct Session(Arc<AtomicUsize>);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A decoder counted against its GPU in the pool until dropped. It can
+/// outlive the pool.
+pub struct PooledDecoder {
+    decoder: Decoder,
+    gpu_id: usize,
+    _session: Session,
+}
+
+impl PooledDecoder {
+    pub fn gpu_id(&self) -> usize {
+        self.gpu_id
+    }
+}
+
+impl Deref for PooledDecoder {
+    type Target = Decoder;
+
+    fn deref(&self) -> &Decoder {
+        &self.decoder
+    }
+}
+
+impl DerefMut for PooledDecoder {
+    fn deref_mut(&mut self) -> &mut Decoder {
+        &mut self.decoder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpu(gpu_id: usize, sessions: usize, free_memory: u64) -> GpuStats {
+        GpuStats {
+            gpu_id,
+            sessions,
+            free_memory,
+            total_memory: 16 << 30,
+        }
+    }
+
+    #[test]
+    fn placements() {
+        let gpus = [gpu(0, 3, 8 << 30), gpu(1, 2, 4 << 30), gpu(2, 2, 6 << 30)];
+
+        assert_eq!(LeastSessions.place(&gpus), Some(2));
+        assert_eq!(MostFreeMemory.place(&gpus), Some(0));
+        assert_eq!(LeastSessions.place(&[]), None);
+
+        let last = |gpus: &[GpuStats]| gpus.len().checked_sub(1);
+        assert_eq!(last.place(&gpus), Some(2));
+    }
+}
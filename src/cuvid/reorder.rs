@@ -0,0 +1,89 @@
+/// Holds back up to `depth` items to hand them out in ascending timestamp
+/// order, see `DecoderBuilder::pts_order`.
+#[derive(Debug)]
+pub(crate) struct ReorderBuffer<T> {
+    depth: usize,
+    /// Sorted by timestamp, equal ones in arrival order.
+    pending: Vec<(i64, T)>,
+    /// Timestamp of the last item handed out.
+    last: Option<i64>,
+}
+
+impl<T> ReorderBuffer<T> {
+    pub(crate) fn new(depth: usize) -> Self {
+        ReorderBuffer {
+            depth,
+            pending: Vec::with_capacity(depth + 1),
+            last: None,
+        }
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Adds `item`, returning the earliest one once more than `depth` are
+    /// held. An item stamped before the last one handed out can't go in
+    /// order anymore and is given back as an error.
+    pub(crate) fn push(&mut self, timestamp: i64, item: T) -> Result<Option<T>, T> {
+        if self.last.is_some_and(|last| timestamp < last) {
+            return Err(item);
+        }
+
+        let at = self.pending.partition_point(|&(ts, _)| ts <= timestamp);
+        self.pending.insert(at, (timestamp, item));
+        if self.pending.len() <= self.depth {
+            return Ok(None);
+        }
+
+        let (timestamp, item) = self.pending.remove(0);
+        self.last = Some(timestamp);
+        Ok(Some(item))
+    }
+
+    /// Everything held, in order, at the end of the stream.
+    pub(crate) fn drain(&mut self) -> Vec<T> {
+        if let Some(&(timestamp, _)) = self.pending.last() {
+            self.last = Some(timestamp);
+        }
+
+        self.pending.drain(..).map(|(_, item)| item).collect()
+    }
+
+    /// Everything held, starting over with the next item.
+    pub(crate) fn reset(&mut self) -> Vec<T> {
+        self.last = None;
+
+        self.pending.drain(..).map(|(_, item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascending_order() {
+        let mut buffer = ReorderBuffer::new(2);
+        let out: Vec<Result<Option<i64>, i64>> = [10, 30, 20, 40, 15, 50]
+            .iter()
+            .map(|&ts| buffer.push(ts, ts))
+            .collect();
+        assert_eq!(
+            out,
+            [
+                Ok(None),
+                Ok(None),
+                Ok(Some(10)),
+                Ok(Some(20)),
+                Err(15),
+                Ok(Some(30))
+            ]
+        );
+        assert_eq!(buffer.drain(), [40, 50]);
+        assert_eq!(buffer.push(45, 45), Err(45));
+
+        assert!(buffer.reset().is_empty());
+        assert_eq!(buffer.push(0, 0), Ok(None));
+    }
+}
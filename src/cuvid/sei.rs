@@ -0,0 +1,194 @@
+use super::{ffi, Orientation, Rotation};
+
+/// A supplemental enhancement information message of the picture, e.g. user
+/// data or a timecode.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SeiMessage {
+    /// The SEI payload type, 5 for unregistered user data, 136 for HEVC
+    /// timecodes etc.
+    pub payload_type: u8,
+    pub payload: Vec<u8>,
+}
+
+/// SMPTE ST 2086 mastering display colour volume, SEI payload type 137.
+///
+/// Chromaticities are in units of 0.00002, luminances in 0.0001 cd/m².
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MasteringDisplay {
+    /// Green, blue and red primaries as (x, y).
+    pub primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+/// Content light level information, SEI payload type 144. Both values are
+/// in cd/m².
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ContentLightLevel {
+    pub max_content_light_level: u16,
+    pub max_frame_average_light_level: u16,
+}
+
+fn be16(data: &[u8], at: usize) -> u16 {
+    (u16::from(data[at]) << 8) | u16::from(data[at + 1])
+}
+
+fn be32(data: &[u8], at: usize) -> u32 {
+    (u32::from(be16(data, at)) << 16) | u32::from(be16(data, at + 2))
+}
+
+impl SeiMessage {
+    pub fn mastering_display(&self) -> Option<MasteringDisplay> {
+        let data = &self.payload;
+        if self.payload_type != 137 || data.len() < 24 {
+            return None;
+        }
+
+        Some(MasteringDisplay {
+            primaries: [
+                (be16(data, 0), be16(data, 2)),
+                (be16(data, 4), be16(data, 6)),
+                (be16(data, 8), be16(data, 10)),
+            ],
+            white_point: (be16(data, 12), be16(data, 14)),
+            max_luminance: be32(data, 16),
+            min_luminance: be32(data, 20),
+        })
+    }
+
+    /// The orientation signalled by a display orientation message, payload
+    /// type 47, upright if it cancels the previous one.
+    pub fn display_orientation(&self) -> Option<Orientation> {
+        let data = &self.payload;
+        if self.payload_type != 47 || data.is_empty() {
+            return None;
+        }
+        if data[0] & 0x80 != 0 {
+            return Some(Orientation::default());
+        }
+        if data.len() < 3 {
+            return None;
+        }
+
+        let hor_flip = data[0] & 0x40 != 0;
+        let ver_flip = data[0] & 0x20 != 0;
+        let anticlockwise = (u32::from(data[0] & 0x1f) << 11)
+            | (u32::from(data[1]) << 3)
+            | (u32::from(data[2]) >> 5);
+        // In units of 360 / 2^16 degrees. A vertical flip is a horizontal
+        // one rotated by 180 degrees.
+        let degrees = -((anticlockwise * 360 / 65536) as i32) + if ver_flip { 180 } else { 0 };
+
+        Some(Orientation::new(
+            Rotation::from_degrees(degrees),
+            hor_flip != ver_flip,
+        ))
+    }
+
+    pub fn content_light_level(&self) -> Option<ContentLightLevel> {
+        if self.payload_type != 144 || self.payload.len() < 4 {
+            return None;
+        }
+
+        Some(ContentLightLevel {
+            max_content_light_level: be16(&self.payload, 0),
+            max_frame_average_light_level: be16(&self.payload, 2),
+        })
+    }
+}
+
+/// Copies the messages out of the parser's buffers, which are only valid
+/// during the callback.
+pub(crate) fn collect(info: &ffi::cuvid::CUVIDSEIMESSAGEINFO) -> Vec<SeiMessage> {
+    if info.pSEIMessage.is_null() || info.pSEIData.is_null() {
+        return Vec::new();
+    }
+
+    let (messages, data) = unsafe {
+        let messages =
+            std::slice::from_raw_parts(info.pSEIMessage, info.sei_message_count as usize);
+        let size = messages.iter().map(|m| m.sei_message_size as usize).sum();
+        let data = std::slice::from_raw_parts(info.pSEIData as *const u8, size);
+
+        (messages, data)
+    };
+
+    let mut offset = 0;
+    messages
+        .iter()
+        .map(|m| {
+            let size = m.sei_message_size as usize;
+            let payload = data[offset..offset + size].to_vec();
+            offset += size;
+
+            SeiMessage {
+                payload_type: m.sei_message_type,
+                payload,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdr10_metadata() {
+        // BT.2020 primaries, D65, 1000 / 0.005 cd/m².
+        let mastering = SeiMessage {
+            payload_type: 137,
+            payload: vec![
+                0x21, 0x34, 0x9b, 0xaa, 0x19, 0x96, 0x08, 0xfc, 0x84, 0xd0, 0x3e, 0x80, 0x3d, 0x13,
+                0x40, 0x42, 0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x32,
+            ],
+        };
+        let display = mastering.mastering_display().unwrap();
+        assert_eq!(display.primaries[0], (8500, 39850));
+        assert_eq!(display.white_point, (15635, 16450));
+        assert_eq!(display.max_luminance, 10_000_000);
+        assert_eq!(display.min_luminance, 50);
+        assert_eq!(mastering.content_light_level(), None);
+
+        let light = SeiMessage {
+            payload_type: 144,
+            payload: vec![0x03, 0xe8, 0x01, 0x90],
+        };
+        assert_eq!(
+            light.content_light_level(),
+            Some(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_frame_average_light_level: 400,
+            })
+        );
+    }
+
+    #[test]
+    fn display_orientation() {
+        // 90 degrees anticlockwise, i.e. 0x4000, then cancelled.
+        let rotated = SeiMessage {
+            payload_type: 47,
+            payload: vec![0x08, 0x00, 0x02],
+        };
+        assert_eq!(
+            rotated.display_orientation(),
+            Some(Orientation::new(Rotation::Clockwise270, false))
+        );
+
+        let flipped = SeiMessage {
+            payload_type: 47,
+            payload: vec![0x20, 0x00, 0x02],
+        };
+        assert_eq!(
+            flipped.display_orientation(),
+            Some(Orientation::new(Rotation::Rotate180, true))
+        );
+
+        let cancel = SeiMessage {
+            payload_type: 47,
+            payload: vec![0x80],
+        };
+        assert_eq!(cancel.display_orientation(), Some(Orientation::default()));
+    }
+}
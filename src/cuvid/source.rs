@@ -0,0 +1,158 @@
+//! Decoding a whole stream without handling the decoder directly.
+
+use std::time::Duration;
+
+use super::{Codec, DecodeError, Decoder, DecoderBuilder, GpuFrame, PacketFlags};
+
+/// One packet of an elementary stream.
+#[derive(Clone, Debug, Default)]
+pub struct Packet {
+    pub data: Vec<u8>,
+    pub timestamp: i64,
+    pub flags: PacketFlags,
+}
+
+/// Where `decode` reads the stream from, e.g. a demuxer.
+pub trait PacketSource {
+    fn codec(&self) -> Codec;
+
+    /// Codec headers carried out of band, see `Decoder::set_extradata`.
+    fn extradata(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// The next packet, none at the end of the stream.
+    fn next_packet(&mut self) -> Option<Packet>;
+}
+
+/// A `PacketSource` over an iterator of packet data and timestamps.
+pub struct Packets<I> {
+    codec: Codec,
+    packets: I,
+}
+
+impl<I> Packets<I> {
+    pub fn new(codec: Codec, packets: I) -> Self {
+        Packets { codec, packets }
+    }
+}
+
+impl<I, D> PacketSource for Packets<I>
+where
+    I: Iterator<Item = (D, i64)>,
+    D: Into<Vec<u8>>,
+{
+    fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    fn next_packet(&mut self) -> Option<Packet> {
+        self.packets.next().map(|(data, timestamp)| Packet {
+            data: data.into(),
+            timestamp,
+            flags: PacketFlags::empty(),
+        })
+    }
+}
+
+/// Decodes everything `source` holds on GPU 0 with the default settings,
+/// for when the context, the parser callbacks and the frame channel need
+/// no tuning.
+/// Corrupted pictures are left out, see `Decoder::drops`.
+///
+/// ```no_run
+/// # use nvidia_video_codec::prelude::*;
+/// # fn run(packets: Vec<(Vec<u8>, i64)>) -> Result<(), DecodeError> {
+/// for frame in decode(Packets::new(Codec::H264, packets.into_iter()))? {
+///     let frame = frame?;
+///     println!("{}x{} at {}", frame.width, frame.height, frame.timestamp);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn decode<S: PacketSource>(source: S) -> Result<Decode<S>, DecodeError> {
+    decode_with(Decoder::builder(source.codec()), source)
+}
+
+/// `decode` with a decoder built from `builder`. Its picture buffer must
+/// be unbounded or drop on overflow, as frames are only received between
+/// packets.
+pub fn decode_with<S: PacketSource>(
+    builder: DecoderBuilder,
+    source: S,
+) -> Result<Decode<S>, DecodeError> {
+    let decoder = builder.build()?;
+    if let Some(extradata) = source.extradata() {
+        decoder.set_extradata(extradata)?;
+    }
+
+    Ok(Decode {
+        decoder,
+        source,
+        draining: false,
+        done: false,
+    })
+}
+
+/// The frames of a stream, feeding the decoder from the source whenever
+/// none is ready.
+pub struct Decode<S> {
+    decoder: Decoder,
+    source: S,
+    draining: bool,
+    done: bool,
+}
+
+impl<S> Decode<S> {
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+}
+
+impl<S: PacketSource> Iterator for Decode<S> {
+    type Item = Result<GpuFrame, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let mut frames = self.decoder.frames(None);
+            // Frames come out while queueing, so only wait once the whole
+            // stream is in.
+            frames.frame_timeout = if self.draining {
+                None
+            } else {
+                Some(Duration::ZERO)
+            };
+
+            match frames.try_next() {
+                Ok(frame) => return Some(Ok(frame)),
+                Err(DecodeError::Timeout) | Err(DecodeError::Corrupted { .. }) => {}
+                Err(DecodeError::Eos) => self.done = true,
+                Err(err) => {
+                    self.done = err.ends_stream();
+                    return Some(Err(err));
+                }
+            }
+            if self.draining || self.done {
+                continue;
+            }
+
+            let res = match self.source.next_packet() {
+                Some(packet) => {
+                    self.decoder
+                        .queue_with_flags(&packet.data, packet.timestamp, packet.flags)
+                }
+                None => {
+                    self.draining = true;
+                    self.decoder.send_eos()
+                }
+            };
+            if let Err(err) = res {
+                // A failed end of stream never closes the channel.
+                self.done = self.draining || err.ends_stream();
+                return Some(Err(err));
+            }
+        }
+
+        None
+    }
+}
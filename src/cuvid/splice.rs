@@ -0,0 +1,112 @@
+//! Switching the output between two inputs at a given timestamp, e.g. for
+//! ad insertion or failing over to a backup camera.
+
+use super::{DecodeError, Decoder, GpuFrame};
+
+/// Decodes two inputs side by side and outputs the frames of one of them
+/// at a time, with timestamps running on across switches.
+///
+/// The input being switched to has to be queued from a random access point
+/// with its parameter sets (the extradata) in front, early enough for its
+/// first frames to be ready at the splice point. Frames of that input before
+/// its in point are discarded. The input being left is flushed once the
+/// switch happens, so it must be primed the same way again before switching
+/// back to it. If it ends before reaching its out point the switch happens
+/// right away.
+pub struct Splicer {
+    inputs: [Decoder; 2],
+    active: usize,
+    offset: i64,
+    pending: Option<(i64, i64)>,
+    skip_until: Option<i64>,
+    next_pts: Option<i64>,
+}
+
+impl Splicer {
+    /// Starts with the output of `first`.
+    pub fn new(first: Decoder, second: Decoder) -> Self {
+        Splicer {
+            inputs: [first, second],
+            active: 0,
+            offset: 0,
+            pending: None,
+            skip_until: None,
+            next_pts: None,
+        }
+    }
+
+    /// Index of the input currently output.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn decoder(&self, input: usize) -> &Decoder {
+        &self.inputs[input]
+    }
+
+    pub fn queue(&self, input: usize, data: &[u8], timestamp: i64) -> Result<(), DecodeError> {
+        self.inputs[input].queue(data, timestamp)
+    }
+
+    /// Switches to the other input once the active one reaches `out_pts`.
+    /// The other input's frame at `in_pts` follows the last frame before
+    /// `out_pts`, both in the timestamps of the respective input.
+    pub fn splice(&mut self, out_pts: i64, in_pts: i64) {
+        self.pending = Some((out_pts, in_pts));
+    }
+
+    /// The next frame of the output, stamped on the output's time line.
+    pub fn try_next(&mut self) -> Result<GpuFrame, DecodeError> {
+        loop {
+            let mut frame = match self.inputs[self.active].frames(None).try_next() {
+                Ok(frame) => frame,
+                Err(DecodeError::Eos) if self.pending.is_some() => {
+                    let out_pts = self.pending.unwrap().0;
+                    let position = self.next_pts.map_or(out_pts, |pts| pts.min(out_pts));
+                    self.switch(position)?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if let Some((out_pts, _)) = self.pending {
+                if frame.timestamp >= out_pts {
+                    drop(frame);
+                    self.switch(out_pts)?;
+                    continue;
+                }
+            }
+
+            if let Some(in_pts) = self.skip_until {
+                if frame.timestamp < in_pts {
+                    continue;
+                }
+                self.skip_until = None;
+            }
+
+            self.next_pts = frame.interval.map(|interval| frame.timestamp + interval);
+            frame.timestamp += self.offset;
+
+            return Ok(frame);
+        }
+    }
+
+    /// Continues with the other input at `position` of the active one.
+    fn switch(&mut self, position: i64) -> Result<(), DecodeError> {
+        let (_, in_pts) = self.pending.take().unwrap();
+        tracing::debug!(
+            "splicing from input {} at {} to {}",
+            self.active,
+            position,
+            in_pts
+        );
+
+        self.inputs[self.active].flush()?;
+        self.offset += position - in_pts;
+        self.active = 1 - self.active;
+        self.skip_until = Some(in_pts);
+        self.next_pts = None;
+
+        Ok(())
+    }
+}
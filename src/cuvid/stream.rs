@@ -0,0 +1,185 @@
+//! Receiving frames from async code, with the `async` feature.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::decoder::{FramesIter, PreparedFrame};
+use super::{DecodeError, GpuFrame};
+
+/// Wakes the task once `at` has passed, from a thread of its own so that no
+/// particular runtime is needed.
+struct Deadline {
+    at: Instant,
+    waker: Arc<Mutex<Option<Waker>>>,
+    started: bool,
+}
+
+impl Deadline {
+    fn new(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now() + timeout,
+            waker: Arc::new(Mutex::new(None)),
+            started: false,
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.at {
+            return Poll::Ready(());
+        }
+
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        if !self.started {
+            self.started = true;
+            let at = self.at;
+            let waker = Arc::clone(&self.waker);
+            std::thread::spawn(move || {
+                std::thread::sleep(at.saturating_duration_since(Instant::now()));
+                if let Some(waker) = waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            });
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        self.waker.lock().unwrap().take();
+    }
+}
+
+/// The receiving half shared by the futures and streams of this module.
+///
+/// A frame only leaves the channel in the poll that maps and returns it,
+/// so dropping a pending receive loses nothing.
+struct Recv<'a, 'b> {
+    frames: FramesIter<'a, 'b>,
+    recv: Option<flume::r#async::RecvFut<'a, PreparedFrame>>,
+    deadline: Option<Deadline>,
+}
+
+impl<'a, 'b> Recv<'a, 'b> {
+    fn new(frames: FramesIter<'a, 'b>) -> Self {
+        Recv {
+            frames,
+            recv: None,
+            deadline: None,
+        }
+    }
+
+    /// The next frame, none at the end of the stream.
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<GpuFrame>, DecodeError>> {
+        loop {
+            let receiver = self.frames.receiver();
+            let recv = self.recv.get_or_insert_with(|| receiver.recv_async());
+
+            match Pin::new(recv).poll(cx) {
+                Poll::Ready(Ok(frame)) => {
+                    self.recv = None;
+                    if !self.frames.keep(&frame) {
+                        continue;
+                    }
+                    self.deadline = None;
+
+                    return Poll::Ready(self.frames.map_frame(frame).map(Some));
+                }
+                Poll::Ready(Err(_)) => {
+                    self.recv = None;
+                    self.deadline = None;
+
+                    return Poll::Ready(match self.frames.disconnected() {
+                        DecodeError::Eos => Ok(None),
+                        err => Err(err),
+                    });
+                }
+                Poll::Pending => {}
+            }
+
+            let timeout = match self.frames.frame_timeout {
+                Some(timeout) => timeout,
+                None => return Poll::Pending,
+            };
+            let deadline = self.deadline.get_or_insert_with(|| Deadline::new(timeout));
+
+            return match deadline.poll(cx) {
+                Poll::Ready(()) => {
+                    self.deadline = None;
+                    Poll::Ready(Err(DecodeError::Timeout))
+                }
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+/// The frames of a decoder as a `Stream`, see `Decoder::stream`.
+pub struct FrameStream<'a, 'b> {
+    recv: Recv<'a, 'b>,
+    done: bool,
+}
+
+impl<'a, 'b> FrameStream<'a, 'b> {
+    pub(crate) fn new(frames: FramesIter<'a, 'b>) -> Self {
+        FrameStream {
+            recv: Recv::new(frames),
+            done: false,
+        }
+    }
+}
+
+impl futures_core::Stream for FrameStream<'_, '_> {
+    type Item = Result<GpuFrame, DecodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        match this.recv.poll_recv(cx) {
+            Poll::Ready(Ok(Some(frame))) => Poll::Ready(Some(Ok(frame))),
+            Poll::Ready(Ok(None)) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(Err(err)) => {
+                this.done = err.ends_stream();
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl futures_core::FusedStream for FrameStream<'_, '_> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+/// The next frame of a decoder, see `Decoder::next_frame`.
+pub struct NextFrame<'a> {
+    recv: Recv<'a, 'static>,
+}
+
+impl<'a> NextFrame<'a> {
+    pub(crate) fn new(frames: FramesIter<'a, 'static>) -> Self {
+        NextFrame {
+            recv: Recv::new(frames),
+        }
+    }
+}
+
+impl Future for NextFrame<'_> {
+    type Output = Result<Option<GpuFrame>, DecodeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().recv.poll_recv(cx)
+    }
+}
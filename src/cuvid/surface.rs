@@ -7,6 +7,38 @@ pub enum VideoSurfaceFormat {
     P016 = ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_P016,
     YUV444 = ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_YUV444,
     YUV444_16 = ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_YUV444_16Bit,
+    /// Semi-planar 4:2:2, on GPUs decoding 4:2:2 content.
+    NV16 = ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_NV16,
+    P216 = ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_P216,
+}
+
+impl VideoSurfaceFormat {
+    /// Bytes per luma or chroma sample. The 16 bit formats also carry 10 and
+    /// 12 bit content, with the samples in the most significant bits.
+    pub fn bytes_per_sample(self) -> u32 {
+        match self {
+            VideoSurfaceFormat::NV12 | VideoSurfaceFormat::YUV444 | VideoSurfaceFormat::NV16 => 1,
+            VideoSurfaceFormat::P016 | VideoSurfaceFormat::YUV444_16 | VideoSurfaceFormat::P216 => {
+                2
+            }
+        }
+    }
+
+    /// Rows following the luma plane: one interleaved half height plane for
+    /// 4:2:0, a full height one for 4:2:2 and two full planes for 4:4:4.
+    pub fn chroma_rows(self, height: u32) -> u32 {
+        match self {
+            VideoSurfaceFormat::NV12 | VideoSurfaceFormat::P016 => height.div_ceil(2),
+            VideoSurfaceFormat::NV16 | VideoSurfaceFormat::P216 => height,
+            VideoSurfaceFormat::YUV444 | VideoSurfaceFormat::YUV444_16 => height * 2,
+        }
+    }
+
+    /// Size in bytes of a `height` rows high picture with rows of `pitch`
+    /// bytes.
+    pub fn frame_size(self, pitch: u32, height: u32) -> usize {
+        pitch as usize * (height + self.chroma_rows(height)) as usize
+    }
 }
 
 impl Into<ffi::cuvid::cudaVideoSurfaceFormat> for VideoSurfaceFormat {
@@ -30,7 +62,26 @@ impl From<ffi::cuvid::cudaVideoSurfaceFormat> for VideoSurfaceFormat {
             ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_YUV444_16Bit => {
                 VideoSurfaceFormat::YUV444_16
             }
+            ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_NV16 => {
+                VideoSurfaceFormat::NV16
+            }
+            ffi::cuvid::cudaVideoSurfaceFormat_enum_cudaVideoSurfaceFormat_P216 => {
+                VideoSurfaceFormat::P216
+            }
             _ => panic!("Invalid cuda video surface formate"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_sizes() {
+        assert_eq!(VideoSurfaceFormat::NV12.frame_size(256, 9), 256 * 14);
+        assert_eq!(VideoSurfaceFormat::NV16.frame_size(256, 9), 256 * 18);
+        assert_eq!(VideoSurfaceFormat::P216.bytes_per_sample(), 2);
+        assert_eq!(VideoSurfaceFormat::YUV444_16.frame_size(512, 8), 512 * 24);
+    }
+}
@@ -0,0 +1,192 @@
+/// Evens out the spacing of presentation timestamps.
+///
+/// Cameras stamp frames with their own clock, which drifts against the
+/// receiver's and arrives with network jitter on top. The smoother tracks
+/// the frame period and phase like a PLL: each input nudges the prediction
+/// instead of replacing it, so the output advances by a steady period that
+/// slowly follows the real one. Dropped frames leave a gap of whole periods,
+/// and jumps of more than `RESYNC_PERIODS` restart tracking at the new
+/// position. Output timestamps are strictly increasing.
+#[derive(Clone, Debug)]
+pub struct TimestampSmoother {
+    phase_gain: f64,
+    period_gain: f64,
+    period: Option<f64>,
+    next: Option<f64>,
+    last_input: Option<i64>,
+    last_output: Option<i64>,
+}
+
+const RESYNC_PERIODS: f64 = 8.0;
+
+impl Default for TimestampSmoother {
+    fn default() -> Self {
+        Self::with_gains(0.05, 0.005)
+    }
+}
+
+impl TimestampSmoother {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `phase_gain` is the share of each error applied to the predicted
+    /// position, `period_gain` the share applied to the period. Lower values
+    /// smooth more but follow drift more slowly.
+    pub fn with_gains(phase_gain: f64, period_gain: f64) -> Self {
+        TimestampSmoother {
+            phase_gain,
+            period_gain,
+            period: None,
+            next: None,
+            last_input: None,
+            last_output: None,
+        }
+    }
+
+    /// The tracked frame period, once two timestamps have been seen.
+    pub fn period(&self) -> Option<f64> {
+        self.period
+    }
+
+    pub fn reset(&mut self) {
+        self.period = None;
+        self.next = None;
+        self.last_input = None;
+        self.last_output = None;
+    }
+
+    pub fn push(&mut self, timestamp: i64) -> i64 {
+        let output = match (self.period, self.next, self.last_input) {
+            (Some(period), Some(mut next), _) => {
+                let mut error = timestamp as f64 - next;
+
+                if error.abs() > period * RESYNC_PERIODS {
+                    next = timestamp as f64;
+                    error = 0.0;
+                } else if error > period / 2.0 {
+                    // Skip the periods of dropped frames.
+                    next += (error / period).round() * period;
+                    error = timestamp as f64 - next;
+                }
+
+                self.period = Some(period + self.period_gain * error);
+                next += self.phase_gain * error;
+                self.next = Some(next + self.period.unwrap());
+                next.round() as i64
+            }
+            (None, _, Some(last)) if timestamp > last => {
+                let period = (timestamp - last) as f64;
+                self.period = Some(period);
+                self.next = Some(timestamp as f64 + period);
+                timestamp
+            }
+            _ => timestamp,
+        };
+        self.last_input = Some(timestamp);
+
+        let output = match self.last_output {
+            Some(last) if output <= last => last + 1,
+            _ => output,
+        };
+        self.last_output = Some(output);
+
+        output
+    }
+}
+
+/// Spacing of the frame timestamps seen so far, to tell variable frame rate
+/// sources from constant ones.
+#[derive(Clone, Debug, Default)]
+pub struct FrameIntervals {
+    last: Option<i64>,
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl FrameIntervals {
+    /// Share of the shortest interval by which the longest may exceed it
+    /// before the source counts as variable frame rate.
+    const TOLERANCE: i64 = 10;
+
+    /// Records the next timestamp in display order and returns the time
+    /// since the previous one.
+    pub fn push(&mut self, timestamp: i64) -> Option<i64> {
+        let interval = self.last.map(|last| timestamp - last);
+        self.last = Some(timestamp);
+
+        if let Some(interval) = interval.filter(|i| *i > 0) {
+            self.min = Some(self.min.map_or(interval, |min| min.min(interval)));
+            self.max = Some(self.max.map_or(interval, |max| max.max(interval)));
+        }
+
+        interval
+    }
+
+    /// Forgets the previous timestamp, e.g. after a seek, but keeps the
+    /// statistics.
+    pub fn restart(&mut self) {
+        self.last = None;
+    }
+
+    pub fn is_variable(&self) -> bool {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => max - min > min / Self::TOLERANCE,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_jitter_and_follows_drift() {
+        let mut smoother = TimestampSmoother::new();
+        let jitter = [0, 1500, -1200, 800, -2000, 300, 1900, -700];
+        let period = 33_400;
+
+        let outputs: Vec<i64> = (0..600)
+            .map(|i| smoother.push(i * period + jitter[i as usize % jitter.len()]))
+            .collect();
+
+        for pair in outputs[400..].windows(2) {
+            assert!((pair[1] - pair[0] - period).abs() < 300, "{:?}", pair);
+        }
+        assert!((smoother.period().unwrap() - period as f64).abs() < 50.0);
+    }
+
+    #[test]
+    fn gaps_and_jumps() {
+        let mut smoother = TimestampSmoother::new();
+        for i in 0..100 {
+            smoother.push(i * 1000);
+        }
+
+        // Three frames lost.
+        assert_eq!(smoother.push(103_000), 103_000);
+        assert_eq!(smoother.push(104_000), 104_000);
+
+        // The source restarted far ahead.
+        assert_eq!(smoother.push(5_000_000), 5_000_000);
+        assert_eq!(smoother.push(5_001_000), 5_001_000);
+
+        // Going back in time never produces a decreasing timestamp.
+        assert_eq!(smoother.push(0), 5_001_001);
+    }
+
+    #[test]
+    fn detects_variable_frame_rate() {
+        let mut intervals = FrameIntervals::default();
+        assert_eq!(intervals.push(0), None);
+        assert_eq!(intervals.push(40), Some(40));
+        assert_eq!(intervals.push(81), Some(41));
+        assert!(!intervals.is_variable());
+
+        intervals.restart();
+        assert_eq!(intervals.push(1000), None);
+        assert_eq!(intervals.push(1100), Some(100));
+        assert!(intervals.is_variable());
+    }
+}
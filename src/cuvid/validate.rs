@@ -0,0 +1,212 @@
+//! End-to-end smoke test for encoded bitstreams: the packets are run through
+//! a regular NVDEC session and the decoded output is checked against what
+//! the caller expects.
+
+use std::time::Duration;
+
+use super::{Codec, DecodeError, Decoder};
+
+#[derive(Clone, Debug, Default)]
+pub struct Expectations {
+    /// Number of frames the bitstream should decode to.
+    pub frames: Option<usize>,
+    /// Size every decoded frame should have. When unset any size change
+    /// after the first frame is reported.
+    pub size: Option<(u32, u32)>,
+    /// How long to wait for the last frames after end of stream.
+    pub drain_timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Anomaly {
+    FrameCount {
+        expected: usize,
+        actual: usize,
+    },
+    Resolution {
+        timestamp: i64,
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    DecodeErrors(u64),
+    Queue {
+        timestamp: i64,
+        error: DecodeError,
+    },
+    /// Receiving the frames failed, e.g. for a stream the GPU cannot decode.
+    Decode(DecodeError),
+}
+
+/// What follows an error while receiving frames.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Received {
+    /// More frames may be waiting.
+    More,
+    /// Nothing more for now, or at all after end of stream.
+    Idle,
+    /// The stream ended with the error.
+    Failed,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub frames: usize,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+
+    fn check_frame(&mut self, expected: &mut Option<(u32, u32)>, size: (u32, u32), ts: i64) {
+        self.frames += 1;
+
+        match *expected {
+            Some(expected) if expected != size => self.anomalies.push(Anomaly::Resolution {
+                timestamp: ts,
+                expected,
+                actual: size,
+            }),
+            Some(_) => {}
+            None => *expected = Some(size),
+        }
+    }
+
+    fn check_error(&mut self, error: DecodeError) -> Received {
+        match error {
+            // Counted through `decode_errors`, keep going past it.
+            DecodeError::Corrupted { .. } => Received::More,
+            DecodeError::Timeout | DecodeError::Eos => Received::Idle,
+            error => {
+                let ends = error.ends_stream();
+                self.anomalies.push(Anomaly::Decode(error));
+                if ends {
+                    Received::Failed
+                } else {
+                    Received::More
+                }
+            }
+        }
+    }
+}
+
+/// Receives the frames ready within `timeout`, false once the stream
+/// failed.
+fn drain(
+    decoder: &Decoder,
+    timeout: Duration,
+    report: &mut Report,
+    size: &mut Option<(u32, u32)>,
+) -> bool {
+    let mut iter = decoder.frames(None);
+    iter.frame_timeout = Some(timeout);
+
+    loop {
+        match iter.try_next() {
+            Ok(frame) => report.check_frame(size, (frame.width, frame.height), frame.timestamp),
+            Err(error) => match report.check_error(error) {
+                Received::More => {}
+                Received::Idle => return true,
+                Received::Failed => return false,
+            },
+        }
+    }
+}
+
+/// Decodes `packets` on `gpu_id` and reports any deviation from `expected`.
+///
+/// Errors are only returned when the decode session itself cannot be set up.
+pub fn validate<'p, I>(
+    gpu_id: usize,
+    codec: Codec,
+    packets: I,
+    expected: &Expectations,
+) -> Result<Report, DecodeError>
+where
+    I: IntoIterator<Item = (&'p [u8], i64)>,
+{
+    let decoder = Decoder::builder(codec).gpu(gpu_id).build()?;
+
+    let mut report = Report::default();
+    let mut size = expected.size;
+
+    let mut running = true;
+    for (data, timestamp) in packets {
+        if let Err(error) = decoder.queue(data, timestamp) {
+            running = !error.ends_stream();
+            report.anomalies.push(Anomaly::Queue { timestamp, error });
+        }
+
+        running = running && drain(&decoder, Duration::from_millis(0), &mut report, &mut size);
+        if !running {
+            break;
+        }
+    }
+
+    if running {
+        decoder.send_eos()?;
+
+        let timeout = expected.drain_timeout.unwrap_or(Duration::from_secs(5));
+        drain(&decoder, timeout, &mut report, &mut size);
+    }
+
+    let errors = decoder.decode_errors();
+    if errors > 0 {
+        report.anomalies.push(Anomaly::DecodeErrors(errors));
+    }
+
+    if let Some(frames) = expected.frames {
+        if frames != report.frames {
+            report.anomalies.push(Anomaly::FrameCount {
+                expected: frames,
+                actual: report.frames,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::UnsupportedStream;
+    use super::*;
+
+    #[test]
+    fn resolution_changes_are_reported() {
+        let mut report = Report::default();
+        let mut size = None;
+
+        report.check_frame(&mut size, (1920, 1080), 0);
+        report.check_frame(&mut size, (1920, 1080), 1);
+        report.check_frame(&mut size, (1280, 720), 2);
+
+        assert_eq!(report.frames, 3);
+        assert_eq!(
+            report.anomalies,
+            vec![Anomaly::Resolution {
+                timestamp: 2,
+                expected: (1920, 1080),
+                actual: (1280, 720),
+            }]
+        );
+    }
+
+    #[test]
+    fn decode_failures_are_reported() {
+        let mut report = Report::default();
+
+        assert_eq!(
+            report.check_error(DecodeError::Corrupted { timestamp: 0 }),
+            Received::More
+        );
+        assert_eq!(report.check_error(DecodeError::Timeout), Received::Idle);
+        assert!(report.is_ok());
+
+        let unsupported = DecodeError::Unsupported(UnsupportedStream::OutputFormat);
+        assert_eq!(report.check_error(unsupported), Received::Failed);
+        assert_eq!(report.anomalies, vec![Anomaly::Decode(unsupported)]);
+        assert!(!report.is_ok());
+    }
+}
@@ -0,0 +1,163 @@
+//! Distributing decoded frames to several consumers, e.g. a recorder, an
+//! analyzer and a preview, without letting the slowest one hold on to the
+//! decoder surfaces.
+//!
+//! ```no_run
+//! # use nvidia_video_codec::fanout::{DropPolicy, Fanout};
+//! # fn run(decoder: &nvidia_video_codec::cuvid::Decoder) {
+//! let mut fanout = Fanout::new();
+//! let recorder = fanout.add_sink(16, DropPolicy::Block);
+//! let preview = fanout.add_sink(1, DropPolicy::DropOldest);
+//!
+//! let current = decoder.context().push().unwrap();
+//! for frame in decoder.frames(None) {
+//!     fanout.send(&frame, &current, None).unwrap();
+//! }
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use cuda::context::CurrentContext;
+use cuda::stream::CuStream;
+use cuvid::{DeviceFrame, GpuFrame};
+use ffi::cuda::CUresult;
+
+/// What happens to a frame when a sink's queue is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DropPolicy {
+    /// The new frame is not delivered to that sink.
+    DropNewest,
+    /// The oldest queued frame makes room for the new one.
+    DropOldest,
+    /// `Fanout::send` waits until the sink catches up.
+    Block,
+}
+
+struct Sink {
+    policy: DropPolicy,
+    sender: flume::Sender<Arc<DeviceFrame>>,
+    receiver: flume::Receiver<Arc<DeviceFrame>>,
+    dropped: u64,
+}
+
+/// Copies every frame once and hands the copy to all the sinks, each with
+/// a bounded queue of its own.
+///
+/// Copies are pooled: one is reused as soon as no sink holds it anymore, so
+/// they are allocated and freed on the thread calling `send`, in the
+/// context given to it. Sinks whose receiver is gone are
+/// skipped.
+#[derive(Default)]
+pub struct Fanout {
+    sinks: Vec<Sink>,
+    pool: Vec<Arc<DeviceFrame>>,
+}
+
+impl Fanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sink receiving at most `capacity` queued frames.
+    pub fn add_sink(
+        &mut self,
+        capacity: usize,
+        policy: DropPolicy,
+    ) -> flume::Receiver<Arc<DeviceFrame>> {
+        let (sender, receiver) = flume::bounded(capacity.max(1));
+        self.sinks.push(Sink {
+            policy,
+            sender,
+            receiver: receiver.clone(),
+            dropped: 0,
+        });
+
+        receiver
+    }
+
+    /// Number of sinks added, connected or not.
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Frames the `sink`-th sink missed because its queue was full.
+    pub fn dropped(&self, sink: usize) -> u64 {
+        self.sinks[sink].dropped
+    }
+
+    /// Copies `frame` and queues the copy on every connected sink. The
+    /// decoder surface can be released as soon as this returns. `current`
+    /// must be the context the frame is mapped in.
+    pub fn send(
+        &mut self,
+        frame: &GpuFrame,
+        current: &CurrentContext,
+        stream: Option<&CuStream>,
+    ) -> Result<(), CUresult> {
+        if self.sinks.iter().all(|sink| sink.sender.is_disconnected()) {
+            return Ok(());
+        }
+
+        let copy = self.copy(frame, current, stream)?;
+
+        for sink in &mut self.sinks {
+            if sink.sender.is_disconnected() {
+                continue;
+            }
+
+            let lost = match sink.policy {
+                DropPolicy::Block => {
+                    let _ = sink.sender.send(Arc::clone(&copy));
+                    false
+                }
+                DropPolicy::DropNewest => sink.sender.try_send(Arc::clone(&copy)).is_err(),
+                DropPolicy::DropOldest => match sink.sender.try_send(Arc::clone(&copy)) {
+                    Err(flume::TrySendError::Full(copy)) => {
+                        let evicted = sink.receiver.try_recv().is_ok();
+                        sink.sender.try_send(copy).is_err() || evicted
+                    }
+                    _ => false,
+                },
+            };
+
+            if lost {
+                sink.dropped += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        frame: &GpuFrame,
+        current: &CurrentContext,
+        stream: Option<&CuStream>,
+    ) -> Result<Arc<DeviceFrame>, CUresult> {
+        // Copies of another size are of no use anymore once they are free.
+        self.pool.retain(|copy| {
+            Arc::strong_count(copy) > 1 || (copy.width, copy.height) == (frame.width, frame.height)
+        });
+
+        let free = self.pool.iter_mut().find(|copy| {
+            Arc::strong_count(copy) == 1 && (copy.width, copy.height) == (frame.width, frame.height)
+        });
+
+        let copy = match free {
+            Some(copy) => copy,
+            None => {
+                self.pool
+                    .push(Arc::new(DeviceFrame::new(frame.width, frame.height)?));
+                self.pool.last_mut().unwrap()
+            }
+        };
+        frame.copy_into(Arc::get_mut(copy).unwrap(), current, stream)?;
+
+        Ok(Arc::clone(copy))
+    }
+}
@@ -0,0 +1,588 @@
+//! Small chains of NPP operations applied to NV12 pictures before they are
+//! handed to an encoder or an inference stage.
+//!
+//! ```no_run
+//! # use nvidia_video_codec::filter::Chain;
+//! # fn run(decoder: &nvidia_video_codec::cuvid::Decoder,
+//! #        frame: &nvidia_video_codec::cuvid::GpuFrame) {
+//! let mut chain = Chain::new().crop(0, 140, 1920, 800).resize(1280, 534).sharpen();
+//! let current = decoder.context().push().unwrap();
+//! let output = chain.run_frame(frame, &current, None).unwrap();
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use super::{ffi, NppResult};
+use cuda::context::CurrentContext;
+use cuda::stream::CuStream;
+use cuvid::{scale_planes, CUdeviceptr, DeviceFrame, GpuFrame, Orientation, VideoSurfaceFormat};
+use quality::Error;
+
+#[derive(Clone)]
+enum Op {
+    Crop {
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    },
+    Resize(u32, u32),
+    Denoise,
+    Sharpen,
+    Overlay {
+        frame: Arc<DeviceFrame>,
+        x: u32,
+        y: u32,
+    },
+    /// The given orientation, or the frame's own.
+    Orient(Option<Orientation>),
+}
+
+/// The picture flowing through the chain: either the caller's input or one
+/// of the chain's own buffers.
+#[derive(Clone, Copy)]
+struct View {
+    luma: CUdeviceptr,
+    chroma: CUdeviceptr,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    owned: Option<usize>,
+}
+
+impl View {
+    /// The `width`x`height` window at `left`, `top`, in the same memory.
+    fn crop(&self, left: u32, top: u32, width: u32, height: u32) -> Result<View, Error> {
+        if left + width > self.width || top + height > self.height {
+            return Err(Error::Npp(ffi::npp::NppStatus_NPP_SIZE_ERROR));
+        }
+
+        Ok(View {
+            luma: self.luma + (top * self.pitch + left) as u64,
+            chroma: self.chroma + (top / 2 * self.pitch + left) as u64,
+            width,
+            height,
+            ..*self
+        })
+    }
+
+    /// Whether the view is all of `whole`, not a window into it.
+    fn covers(&self, whole: &View) -> bool {
+        self.luma == whole.luma && self.width == whole.width && self.height == whole.height
+    }
+}
+
+/// An ordered list of operations executed on one stream for every frame.
+///
+/// Intermediate pictures are allocated on first use in the current context
+/// and reused as long as their size does not change. Crops only move the
+/// window over the previous picture and cost nothing.
+#[derive(Default)]
+pub struct Chain {
+    ops: Vec<Op>,
+    buffers: Vec<Option<DeviceFrame>>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the `width`x`height` window at `left`, `top`. Offsets and sizes
+    /// are rounded down to even values to stay on chroma sample boundaries.
+    pub fn crop(mut self, left: u32, top: u32, width: u32, height: u32) -> Self {
+        self.ops.push(Op::Crop {
+            left: left & !1,
+            top: top & !1,
+            width: width & !1,
+            height: height & !1,
+        });
+        self
+    }
+
+    pub fn resize(mut self, width: u32, height: u32) -> Self {
+        self.ops.push(Op::Resize(width, height));
+        self
+    }
+
+    /// 3x3 gaussian blur of the luma plane.
+    pub fn denoise(mut self) -> Self {
+        self.ops.push(Op::Denoise);
+        self
+    }
+
+    /// 3x3 sharpening of the luma plane.
+    pub fn sharpen(mut self) -> Self {
+        self.ops.push(Op::Sharpen);
+        self
+    }
+
+    /// Copies `frame` opaquely over the picture with its top left corner at
+    /// `x`, `y` (rounded down to even values).
+    pub fn overlay(mut self, frame: Arc<DeviceFrame>, x: u32, y: u32) -> Self {
+        self.ops.push(Op::Overlay {
+            frame,
+            x: x & !1,
+            y: y & !1,
+        });
+        self
+    }
+
+    /// Turns the picture upright according to the orientation of the frame
+    /// given to `run_frame`, so that its output needs no orientation to be
+    /// signalled anymore. Does nothing in `run`.
+    pub fn upright(mut self) -> Self {
+        self.ops.push(Op::Orient(None));
+        self
+    }
+
+    /// Applies `orientation` whatever the frame's, e.g. for pictures not
+    /// coming from the decoder. Both sizes must be even.
+    pub fn orient(mut self, orientation: Orientation) -> Self {
+        self.ops.push(Op::Orient(Some(orientation)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Runs the chain on a decoded frame, in `current`, the context it is
+    /// mapped in.
+    pub fn run_frame(
+        &mut self,
+        frame: &GpuFrame,
+        current: &CurrentContext,
+        stream: Option<&CuStream>,
+    ) -> Result<&DeviceFrame, Error> {
+        if !frame.is_current(current) {
+            return Err(Error::Cuda(
+                ffi::cuda::cudaError_enum_CUDA_ERROR_INVALID_CONTEXT,
+            ));
+        }
+        if frame.format != VideoSurfaceFormat::NV12 {
+            return Err(Error::Npp(ffi::npp::NppStatus_NPP_NOT_SUPPORTED_MODE_ERROR));
+        }
+
+        let upright = self.ops.iter().any(|op| matches!(*op, Op::Orient(None)));
+        let output = self.run_oriented(
+            frame.ptr,
+            frame.pitch,
+            (frame.width, frame.height),
+            frame.orientation,
+            stream,
+        )?;
+        output.timestamp = frame.timestamp;
+        output.orientation = if upright {
+            Orientation::default()
+        } else {
+            frame.orientation
+        };
+
+        Ok(output)
+    }
+
+    /// Runs the chain on the NV12 picture at `ptr`, which is never written
+    /// to. The result stays valid until the next call.
+    pub fn run(
+        &mut self,
+        ptr: CUdeviceptr,
+        pitch: u32,
+        size: (u32, u32),
+        stream: Option<&CuStream>,
+    ) -> Result<&mut DeviceFrame, Error> {
+        self.run_oriented(ptr, pitch, size, Orientation::default(), stream)
+    }
+
+    fn run_oriented(
+        &mut self,
+        ptr: CUdeviceptr,
+        pitch: u32,
+        size: (u32, u32),
+        orientation: Orientation,
+        stream: Option<&CuStream>,
+    ) -> Result<&mut DeviceFrame, Error> {
+        let stream_ctx = super::npp_stream_context(stream)?;
+        self.buffers.resize_with(self.ops.len() + 1, || None);
+
+        let mut view = View {
+            luma: ptr,
+            chroma: ptr + (pitch * size.1) as u64,
+            pitch,
+            width: size.0,
+            height: size.1,
+            owned: None,
+        };
+
+        for (i, op) in self.ops.iter().enumerate() {
+            view = match *op {
+                Op::Crop {
+                    left,
+                    top,
+                    width,
+                    height,
+                } => view.crop(left, top, width, height)?,
+                Op::Resize(width, height) => {
+                    let dst = buffer(&mut self.buffers[i], width, height)?;
+                    scale_planes(
+                        (view.luma, view.chroma),
+                        view.pitch,
+                        (view.width, view.height),
+                        (dst.ptr(), dst.chroma_ptr()),
+                        dst.pitch,
+                        (width, height),
+                        stream,
+                    )?;
+
+                    owned(dst, i)
+                }
+                Op::Denoise | Op::Sharpen => {
+                    let dst = buffer(&mut self.buffers[i], view.width, view.height)?;
+                    let src = view.luma as *const ffi::npp::Npp8u;
+                    let offset = ffi::npp::NppiPoint { x: 0, y: 0 };
+
+                    unsafe {
+                        if let Op::Denoise = *op {
+                            ffi::npp::nppiFilterGaussBorder_8u_C1R_Ctx(
+                                src,
+                                view.pitch as _,
+                                npp_size(view.width, view.height),
+                                offset,
+                                dst.ptr() as *mut ffi::npp::Npp8u,
+                                dst.pitch as _,
+                                npp_size(view.width, view.height),
+                                ffi::npp::NppiMaskSize_NPP_MASK_SIZE_3_X_3,
+                                ffi::npp::NppiBorderType_NPP_BORDER_REPLICATE,
+                                stream_ctx,
+                            )
+                            .err()?;
+                        } else {
+                            ffi::npp::nppiFilterSharpenBorder_8u_C1R_Ctx(
+                                src,
+                                view.pitch as _,
+                                npp_size(view.width, view.height),
+                                offset,
+                                dst.ptr() as *mut ffi::npp::Npp8u,
+                                dst.pitch as _,
+                                npp_size(view.width, view.height),
+                                ffi::npp::NppiBorderType_NPP_BORDER_REPLICATE,
+                                stream_ctx,
+                            )
+                            .err()?;
+                        }
+                    }
+                    copy_plane(
+                        view.chroma,
+                        view.pitch,
+                        dst.chroma_ptr(),
+                        dst.pitch,
+                        (view.width, view.height.div_ceil(2)),
+                        stream_ctx,
+                    )?;
+
+                    owned(dst, i)
+                }
+                Op::Overlay { ref frame, x, y } => {
+                    if x + frame.width > view.width || y + frame.height > view.height {
+                        return Err(Error::Npp(ffi::npp::NppStatus_NPP_SIZE_ERROR));
+                    }
+
+                    // Never draw on the caller's picture.
+                    let view = match view.owned {
+                        Some(_) => view,
+                        None => {
+                            let dst = buffer(&mut self.buffers[i], view.width, view.height)?;
+                            copy_nv12(&view, dst, stream_ctx)?;
+                            owned(dst, i)
+                        }
+                    };
+
+                    copy_plane(
+                        frame.ptr(),
+                        frame.pitch,
+                        view.luma + (y * view.pitch + x) as u64,
+                        view.pitch,
+                        (frame.width, frame.height),
+                        stream_ctx,
+                    )?;
+                    copy_plane(
+                        frame.chroma_ptr(),
+                        frame.pitch,
+                        view.chroma + (y / 2 * view.pitch + x) as u64,
+                        view.pitch,
+                        (frame.width, frame.height.div_ceil(2)),
+                        stream_ctx,
+                    )?;
+
+                    view
+                }
+                Op::Orient(ref requested) => {
+                    let orientation = requested.unwrap_or(orientation);
+                    if orientation.is_upright() {
+                        view
+                    } else {
+                        if (view.width | view.height) & 1 != 0 {
+                            return Err(Error::Npp(ffi::npp::NppStatus_NPP_SIZE_ERROR));
+                        }
+
+                        let (width, height) = if orientation.transposes() {
+                            (view.height, view.width)
+                        } else {
+                            (view.width, view.height)
+                        };
+                        let dst = buffer(&mut self.buffers[i], width, height)?;
+                        orient_nv12(&view, dst, orientation, stream_ctx)?;
+
+                        owned(dst, i)
+                    }
+                }
+            };
+        }
+
+        let last = self.ops.len();
+        let whole = match view.owned {
+            Some(index) => view.covers(&owned(self.buffers[index].as_ref().unwrap(), index)),
+            None => false,
+        };
+        let index = match view.owned {
+            Some(index) if whole => index,
+            // Only crops (or nothing at all) were applied, or a crop came
+            // last, copy the window out.
+            _ => {
+                let dst = buffer(&mut self.buffers[last], view.width, view.height)?;
+                copy_nv12(&view, dst, stream_ctx)?;
+                last
+            }
+        };
+
+        Ok(self.buffers[index].as_mut().unwrap())
+    }
+}
+
+fn npp_size(width: u32, height: u32) -> ffi::npp::NppiSize {
+    ffi::npp::NppiSize {
+        width: width as _,
+        height: height as _,
+    }
+}
+
+fn buffer(
+    slot: &mut Option<DeviceFrame>,
+    width: u32,
+    height: u32,
+) -> Result<&mut DeviceFrame, Error> {
+    let reuse = match *slot {
+        Some(ref frame) => frame.width == width && frame.height == height,
+        None => false,
+    };
+    if !reuse {
+        *slot = Some(DeviceFrame::new(width, height)?);
+    }
+
+    Ok(slot.as_mut().unwrap())
+}
+
+fn owned(frame: &DeviceFrame, index: usize) -> View {
+    View {
+        luma: frame.ptr(),
+        chroma: frame.chroma_ptr(),
+        pitch: frame.pitch,
+        width: frame.width,
+        height: frame.height,
+        owned: Some(index),
+    }
+}
+
+fn copy_plane(
+    src: CUdeviceptr,
+    src_pitch: u32,
+    dst: CUdeviceptr,
+    dst_pitch: u32,
+    size: (u32, u32),
+    stream_ctx: ffi::npp::NppStreamContext,
+) -> Result<(), Error> {
+    unsafe {
+        ffi::npp::nppiCopy_8u_C1R_Ctx(
+            src as *const ffi::npp::Npp8u,
+            src_pitch as _,
+            dst as *mut ffi::npp::Npp8u,
+            dst_pitch as _,
+            npp_size(size.0, size.1),
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    Ok(())
+}
+
+fn copy_nv12(
+    view: &View,
+    dst: &DeviceFrame,
+    stream_ctx: ffi::npp::NppStreamContext,
+) -> Result<(), Error> {
+    copy_plane(
+        view.luma,
+        view.pitch,
+        dst.ptr(),
+        dst.pitch,
+        (view.width, view.height),
+        stream_ctx,
+    )?;
+    copy_plane(
+        view.chroma,
+        view.pitch,
+        dst.chroma_ptr(),
+        dst.pitch,
+        (view.width, view.height.div_ceil(2)),
+        stream_ctx,
+    )
+}
+
+/// Transposes and / or mirrors both planes, the chroma one as 16 bit
+/// samples to keep U and V together.
+fn orient_nv12(
+    view: &View,
+    dst: &DeviceFrame,
+    orientation: Orientation,
+    stream_ctx: ffi::npp::NppStreamContext,
+) -> Result<(), Error> {
+    let (transpose, left_right, top_bottom) = orientation.steps();
+    let axis = match (left_right, top_bottom) {
+        (true, true) => Some(ffi::npp::NppiAxis_NPP_BOTH_AXIS),
+        (true, false) => Some(ffi::npp::NppiAxis_NPP_VERTICAL_AXIS),
+        (false, true) => Some(ffi::npp::NppiAxis_NPP_HORIZONTAL_AXIS),
+        (false, false) => None,
+    };
+    let luma = npp_size(view.width, view.height);
+    let chroma = npp_size(view.width / 2, view.height / 2);
+    let (dst_luma, dst_chroma) = if transpose {
+        (
+            npp_size(view.height, view.width),
+            npp_size(view.height / 2, view.width / 2),
+        )
+    } else {
+        (luma, chroma)
+    };
+
+    unsafe {
+        if transpose {
+            ffi::npp::nppiTranspose_8u_C1R_Ctx(
+                view.luma as *const ffi::npp::Npp8u,
+                view.pitch as _,
+                dst.ptr() as *mut ffi::npp::Npp8u,
+                dst.pitch as _,
+                luma,
+                stream_ctx,
+            )
+            .err()?;
+            ffi::npp::nppiTranspose_16u_C1R_Ctx(
+                view.chroma as *const ffi::npp::Npp16u,
+                view.pitch as _,
+                dst.chroma_ptr() as *mut ffi::npp::Npp16u,
+                dst.pitch as _,
+                chroma,
+                stream_ctx,
+            )
+            .err()?;
+
+            if let Some(axis) = axis {
+                ffi::npp::nppiMirror_8u_C1IR_Ctx(
+                    dst.ptr() as *mut ffi::npp::Npp8u,
+                    dst.pitch as _,
+                    dst_luma,
+                    axis,
+                    stream_ctx,
+                )
+                .err()?;
+                ffi::npp::nppiMirror_16u_C1IR_Ctx(
+                    dst.chroma_ptr() as *mut ffi::npp::Npp16u,
+                    dst.pitch as _,
+                    dst_chroma,
+                    axis,
+                    stream_ctx,
+                )
+                .err()?;
+            }
+        } else if let Some(axis) = axis {
+            ffi::npp::nppiMirror_8u_C1R_Ctx(
+                view.luma as *const ffi::npp::Npp8u,
+                view.pitch as _,
+                dst.ptr() as *mut ffi::npp::Npp8u,
+                dst.pitch as _,
+                luma,
+                axis,
+                stream_ctx,
+            )
+            .err()?;
+            ffi::npp::nppiMirror_16u_C1R_Ctx(
+                view.chroma as *const ffi::npp::Npp16u,
+                view.pitch as _,
+                dst.chroma_ptr() as *mut ffi::npp::Npp16u,
+                dst.pitch as _,
+                chroma,
+                axis,
+                stream_ctx,
+            )
+            .err()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_windows() {
+        let whole = View {
+            luma: 0x10000,
+            chroma: 0x10000 + 1024 * 720,
+            pitch: 1024,
+            width: 1000,
+            height: 720,
+            owned: Some(0),
+        };
+        assert!(whole.covers(&whole));
+
+        let window = whole.crop(8, 4, 640, 360).unwrap();
+        assert_eq!((window.width, window.height), (640, 360));
+        assert_eq!(window.luma, whole.luma + 4 * 1024 + 8);
+        assert_eq!(window.chroma, whole.chroma + 2 * 1024 + 8);
+        assert_eq!(window.owned, Some(0));
+        // Still inside the buffer, so it has to be copied out.
+        assert!(!window.covers(&whole));
+        assert!(!whole
+            .crop(0, 0, 1000, 720)
+            .unwrap()
+            .crop(0, 2, 1000, 718)
+            .unwrap()
+            .covers(&whole));
+        assert!(whole.crop(0, 0, 1000, 720).unwrap().covers(&whole));
+
+        assert!(whole.crop(400, 0, 640, 360).is_err());
+        assert!(whole.crop(0, 400, 640, 360).is_err());
+    }
+
+    #[test]
+    fn even_windows() {
+        let chain = Chain::new().crop(1, 3, 641, 361).resize(320, 180);
+        assert_eq!(chain.len(), 2);
+        match chain.ops[0] {
+            Op::Crop {
+                left,
+                top,
+                width,
+                height,
+            } => assert_eq!((left, top, width, height), (0, 2, 640, 360)),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,13 +1,24 @@
 use std::cell::RefCell;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub extern crate nvidia_video_codec_sys as ffi;
 
 #[macro_use]
 mod macros;
 
+pub mod convert;
 pub mod cuda;
 pub mod cuvid;
+pub mod fanout;
+pub mod filter;
+pub mod mkv;
+pub mod pacing;
+pub mod prelude;
+pub mod quality;
+pub mod vmaf;
+
+pub use cuvid::{decode, decode_with};
 
 thread_local! {
     static INIT: RefCell<Option<()>> = RefCell::new(None);
@@ -23,6 +34,18 @@ pub fn init() {
     });
 }
 
+static FRAME_WARNINGS: AtomicBool = AtomicBool::new(true);
+
+/// Turns the warnings about single frames off or back on for every decoder,
+/// see `DecoderBuilder::frame_warnings`.
+pub fn set_frame_warnings(enabled: bool) {
+    FRAME_WARNINGS.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn frame_warnings() -> bool {
+    FRAME_WARNINGS.load(Ordering::Relaxed)
+}
+
 pub trait CudaResult {
     fn ok(&self) -> bool;
     fn err(&self) -> Result<(), Self>
@@ -65,6 +88,24 @@ impl NppResult for ffi::npp::NppStatus {
     }
 }
 
+pub(crate) fn npp_stream_context(
+    stream: Option<&cuda::stream::CuStream>,
+) -> Result<ffi::npp::NppStreamContext, ffi::npp::NppStatus> {
+    if let Some(stream) = stream {
+        unsafe {
+            if ffi::npp::nppGetStream() != (stream.stream as _) {
+                ffi::npp::nppSetStream(stream.stream as _);
+            }
+        }
+    }
+
+    unsafe {
+        let mut ctx: MaybeUninit<ffi::npp::NppStreamContext> = MaybeUninit::uninit();
+        ffi::npp::nppGetStreamContext(ctx.as_mut_ptr()).err()?;
+        Ok(ctx.assume_init())
+    }
+}
+
 pub fn nv12_to_rgb24(
     ptr: ffi::cuvid::CUdeviceptr,
     width: u32,
@@ -85,19 +126,7 @@ pub fn nv12_to_rgb24(
         height: height as _,
     };
 
-    if let Some(stream) = stream {
-        unsafe {
-            if ffi::npp::nppGetStream() != (stream.stream as _) {
-                ffi::npp::nppSetStream(stream.stream as _);
-            }
-        }
-    }
-
-    let stream_ctx = unsafe {
-        let mut ctx: MaybeUninit<ffi::npp::NppStreamContext> = MaybeUninit::uninit();
-        ffi::npp::nppGetStreamContext(ctx.as_mut_ptr()).err()?;
-        ctx.assume_init()
-    };
+    let stream_ctx = npp_stream_context(stream)?;
 
     unsafe {
         ffi::npp::nppiNV12ToRGB_8u_P2C3R_Ctx(
@@ -134,19 +163,7 @@ pub fn nv12_to_bgr24(
         height: height as _,
     };
 
-    if let Some(stream) = stream {
-        unsafe {
-            if ffi::npp::nppGetStream() != (stream.stream as _) {
-                ffi::npp::nppSetStream(stream.stream as _);
-            }
-        }
-    }
-
-    let stream_ctx = unsafe {
-        let mut ctx: MaybeUninit<ffi::npp::NppStreamContext> = MaybeUninit::uninit();
-        ffi::npp::nppGetStreamContext(ctx.as_mut_ptr()).err()?;
-        ctx.assume_init()
-    };
+    let stream_ctx = npp_stream_context(stream)?;
 
     unsafe {
         ffi::npp::nppiNV12ToBGR_8u_P2C3R_Ctx(
@@ -0,0 +1,818 @@
+//! A minimal Matroska/WebM muxer for a single H.264, HEVC or AV1 track, to
+//! write encoded streams to directly playable files.
+//!
+//! ```no_run
+//! # use nvidia_video_codec::cuvid::Codec;
+//! # use nvidia_video_codec::mkv::{MkvWriter, Track};
+//! # fn run(packets: Vec<(Vec<u8>, i64, bool)>) -> std::io::Result<()> {
+//! let file = std::fs::File::create("out.mkv")?;
+//! let mut writer = MkvWriter::new(file, Track::new(Codec::H264, 1920, 1080))?;
+//! for (data, timestamp, keyframe) in packets {
+//!     writer.write_packet(&data, timestamp, keyframe)?;
+//! }
+//! writer.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use cuvid::Codec;
+
+const EBML: u32 = 0x1A45_DFA3;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549_A966;
+const TRACKS: u32 = 0x1654_AE6B;
+const CLUSTER: u32 = 0x1F43_B675;
+const CUES: u32 = 0x1C53_BB6B;
+
+/// Marks a size as unknown, or is patched with the real one.
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Timestamps are written in milliseconds.
+const TIMESTAMP_SCALE: u64 = 1_000_000;
+
+/// The stream being muxed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Track {
+    pub codec: Codec,
+    pub width: u32,
+    pub height: u32,
+    /// Units per second of the packet timestamps, 10 MHz like the decoder
+    /// by default.
+    pub clock_rate: u32,
+}
+
+impl Track {
+    pub fn new(codec: Codec, width: u32, height: u32) -> Self {
+        Track {
+            codec,
+            width,
+            height,
+            clock_rate: 10_000_000,
+        }
+    }
+}
+
+struct Layout {
+    segment_data: u64,
+    cues_position: u64,
+    duration: u64,
+}
+
+struct Cluster {
+    size: u64,
+    timestamp: i64,
+}
+
+/// Writes packets in decode order, stamped with their presentation time.
+///
+/// H.264 and HEVC packets are expected in Annex B format and AV1 packets as
+/// temporal units of OBUs with size fields, which is what the encoder
+/// produces. The codec configuration is taken from the parameter sets or
+/// the sequence header of the first packet, which must be a keyframe.
+/// Every keyframe starts a cluster and gets a cue. Sizes, duration and cues
+/// are completed by `finish`; without it the file stays playable but not
+/// seekable.
+pub struct MkvWriter<W: Write + Seek> {
+    out: W,
+    track: Track,
+    layout: Option<Layout>,
+    cluster: Option<Cluster>,
+    cues: Vec<(i64, u64)>,
+    origin: Option<i64>,
+    duration: i64,
+}
+
+impl<W: Write + Seek> MkvWriter<W> {
+    pub fn new(out: W, track: Track) -> io::Result<Self> {
+        match track.codec {
+            Codec::H264 | Codec::HEVC | Codec::AV1 => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "only H.264, HEVC and AV1 can be muxed",
+                ))
+            }
+        }
+
+        Ok(MkvWriter {
+            out,
+            track,
+            layout: None,
+            cluster: None,
+            cues: Vec::new(),
+            origin: None,
+            duration: 0,
+        })
+    }
+
+    pub fn write_packet(&mut self, data: &[u8], timestamp: i64, keyframe: bool) -> io::Result<()> {
+        if self.layout.is_none() {
+            if !keyframe {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the first packet must be a keyframe",
+                ));
+            }
+            let codec_private = codec_private(self.track.codec, data).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no codec configuration in the first keyframe",
+                )
+            })?;
+            self.write_header(&codec_private)?;
+        }
+
+        let origin = *self.origin.get_or_insert(timestamp);
+        let time = (timestamp - origin) * 1000 / i64::from(self.track.clock_rate);
+
+        let relative = match self.cluster {
+            Some(ref cluster) if !keyframe => time - cluster.timestamp,
+            _ => i64::MAX,
+        };
+        let relative = if relative < i64::from(i16::MIN) || relative > i64::from(i16::MAX) {
+            self.start_cluster(time.max(0), keyframe)?;
+            time - time.max(0)
+        } else {
+            relative
+        };
+
+        let payload = match self.track.codec {
+            Codec::AV1 => strip_temporal_delimiters(data),
+            _ => length_prefixed(data),
+        };
+
+        let mut block = vec![0x81];
+        block.extend_from_slice(&(relative as i16).to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0 });
+        let mut header = Vec::new();
+        write_id(&mut header, 0xA3);
+        write_size(&mut header, (block.len() + payload.len()) as u64);
+        self.out.write_all(&header)?;
+        self.out.write_all(&block)?;
+        self.out.write_all(&payload)?;
+
+        self.duration = self.duration.max(time);
+
+        Ok(())
+    }
+
+    /// Completes the file and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.layout.is_none() {
+            self.write_header(&[])?;
+        }
+        self.close_cluster()?;
+        let layout = self.layout.take().unwrap();
+
+        let cues_start = self.out.stream_position()?;
+        let mut cues = Vec::new();
+        for &(time, position) in &self.cues {
+            let mut track_positions = Vec::new();
+            uint(&mut track_positions, 0xF7, 1);
+            uint(&mut track_positions, 0xF1, position);
+            let mut point = Vec::new();
+            uint(&mut point, 0xB3, time as u64);
+            element(&mut point, 0xB7, &track_positions);
+            element(&mut cues, 0xBB, &point);
+        }
+        let mut element_bytes = Vec::new();
+        element(&mut element_bytes, CUES, &cues);
+        self.out.write_all(&element_bytes)?;
+        let end = self.out.stream_position()?;
+
+        self.patch(
+            layout.cues_position,
+            &(cues_start - layout.segment_data).to_be_bytes(),
+        )?;
+        self.patch(layout.duration, &(self.duration as f64).to_be_bytes())?;
+        self.patch(
+            layout.segment_data - 8,
+            &fixed_size(end - layout.segment_data),
+        )?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+
+    fn patch(&mut self, position: u64, bytes: &[u8]) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(position))?;
+        self.out.write_all(bytes)
+    }
+
+    fn write_header(&mut self, codec_private: &[u8]) -> io::Result<()> {
+        let doc_type = match self.track.codec {
+            Codec::AV1 => "webm",
+            _ => "matroska",
+        };
+        let mut ebml = Vec::new();
+        uint(&mut ebml, 0x4286, 1);
+        uint(&mut ebml, 0x42F7, 1);
+        uint(&mut ebml, 0x42F2, 4);
+        uint(&mut ebml, 0x42F3, 8);
+        string(&mut ebml, 0x4282, doc_type);
+        uint(&mut ebml, 0x4287, 4);
+        uint(&mut ebml, 0x4285, 2);
+
+        let mut header = Vec::new();
+        element(&mut header, EBML, &ebml);
+        write_id(&mut header, SEGMENT);
+        header.extend_from_slice(&UNKNOWN_SIZE);
+        let segment_data = self.out.stream_position()? + header.len() as u64;
+
+        let mut info = Vec::new();
+        uint(&mut info, 0x2AD7B1, TIMESTAMP_SCALE);
+        string(&mut info, 0x4D80, "nvidia-video-codec");
+        string(&mut info, 0x5741, "nvidia-video-codec");
+        // Duration last, at a known offset from the end.
+        write_id(&mut info, 0x4489);
+        write_size(&mut info, 8);
+        info.extend_from_slice(&0f64.to_be_bytes());
+
+        let codec_id = match self.track.codec {
+            Codec::H264 => "V_MPEG4/ISO/AVC",
+            Codec::HEVC => "V_MPEGH/ISO/HEVC",
+            _ => "V_AV1",
+        };
+        let mut video = Vec::new();
+        uint(&mut video, 0xB0, u64::from(self.track.width));
+        uint(&mut video, 0xBA, u64::from(self.track.height));
+        let mut entry = Vec::new();
+        uint(&mut entry, 0xD7, 1);
+        uint(&mut entry, 0x73C5, 1);
+        uint(&mut entry, 0x83, 1);
+        uint(&mut entry, 0x9C, 0);
+        string(&mut entry, 0x86, codec_id);
+        if !codec_private.is_empty() {
+            element(&mut entry, 0x63A2, codec_private);
+        }
+        element(&mut entry, 0xE0, &video);
+        let mut tracks = Vec::new();
+        element(&mut tracks, 0xAE, &entry);
+
+        let mut info_element = Vec::new();
+        element(&mut info_element, INFO, &info);
+        let mut tracks_element = Vec::new();
+        element(&mut tracks_element, TRACKS, &tracks);
+
+        // Fixed width positions, so the seek head size does not depend on
+        // them and the cues position can be patched in place.
+        let seek = |id: u32, position: u64| {
+            let mut id_bytes = Vec::new();
+            write_id(&mut id_bytes, id);
+            let mut seek = Vec::new();
+            element(&mut seek, SEEK_ID, &id_bytes);
+            element(&mut seek, SEEK_POSITION, &position.to_be_bytes());
+            let mut bytes = Vec::new();
+            element(&mut bytes, SEEK, &seek);
+            bytes
+        };
+        let seek_head_size = |entries: &[Vec<u8>]| {
+            let mut bytes = Vec::new();
+            element(&mut bytes, SEEK_HEAD, &entries.concat());
+            bytes
+        };
+        let placeholder = seek_head_size(&[seek(INFO, 0), seek(TRACKS, 0), seek(CUES, 0)]);
+        let info_position = placeholder.len() as u64;
+        let tracks_position = info_position + info_element.len() as u64;
+        let seek_head = seek_head_size(&[
+            seek(INFO, info_position),
+            seek(TRACKS, tracks_position),
+            seek(CUES, 0),
+        ]);
+
+        let cues_position = segment_data + seek_head.len() as u64 - 8;
+        let duration = segment_data + tracks_position - 8;
+
+        self.out.write_all(&header)?;
+        self.out.write_all(&seek_head)?;
+        self.out.write_all(&info_element)?;
+        self.out.write_all(&tracks_element)?;
+
+        self.layout = Some(Layout {
+            segment_data,
+            cues_position,
+            duration,
+        });
+
+        Ok(())
+    }
+
+    fn start_cluster(&mut self, timestamp: i64, keyframe: bool) -> io::Result<()> {
+        self.close_cluster()?;
+
+        let start = self.out.stream_position()?;
+        let segment_data = self.layout.as_ref().unwrap().segment_data;
+        if keyframe {
+            self.cues.push((timestamp, start - segment_data));
+        }
+
+        let mut header = Vec::new();
+        write_id(&mut header, CLUSTER);
+        header.extend_from_slice(&UNKNOWN_SIZE);
+        uint(&mut header, 0xE7, timestamp as u64);
+        self.out.write_all(&header)?;
+
+        self.cluster = Some(Cluster {
+            size: start + 4,
+            timestamp,
+        });
+
+        Ok(())
+    }
+
+    fn close_cluster(&mut self) -> io::Result<()> {
+        if let Some(cluster) = self.cluster.take() {
+            let end = self.out.stream_position()?;
+            self.patch(cluster.size, &fixed_size(end - cluster.size - 8))?;
+            self.out.seek(SeekFrom::Start(end))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_id(buf: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    buf.extend_from_slice(&bytes[skip..]);
+}
+
+fn write_size(buf: &mut Vec<u8>, size: u64) {
+    let len = (1..8).find(|&len| size < (1 << (7 * len)) - 1).unwrap_or(8);
+    let marked = size | (1 << (7 * len));
+    buf.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+/// An 8 byte size, for sizes patched after the fact.
+fn fixed_size(size: u64) -> [u8; 8] {
+    (size | (1 << 56)).to_be_bytes()
+}
+
+fn element(buf: &mut Vec<u8>, id: u32, body: &[u8]) {
+    write_id(buf, id);
+    write_size(buf, body.len() as u64);
+    buf.extend_from_slice(body);
+}
+
+fn uint(buf: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(buf, id, &bytes[skip..]);
+}
+
+fn string(buf: &mut Vec<u8>, id: u32, value: &str) {
+    element(buf, id, value.as_bytes());
+}
+
+/// The NAL units of an Annex B access unit, without start codes.
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let mut end = starts.get(n + 1).map_or(data.len(), |&next| next - 3);
+            // Zero bytes before a start code belong to it.
+            while end > start && data[end - 1] == 0 {
+                end -= 1;
+            }
+            &data[start..end]
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    for nal in nal_units(data) {
+        out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    out
+}
+
+/// The OBUs of a temporal unit as (type, whole OBU).
+fn obus(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut obus = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0xF;
+        let mut payload = pos + 1 + usize::from(header & 0x4 != 0);
+
+        let size = if header & 0x2 != 0 {
+            let mut size = 0usize;
+            for i in 0..8 {
+                let byte = match data.get(payload) {
+                    Some(&byte) => byte,
+                    None => return obus,
+                };
+                payload += 1;
+                size |= usize::from(byte & 0x7F) << (7 * i);
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            size
+        } else {
+            data.len().saturating_sub(payload)
+        };
+
+        let end = (payload + size).min(data.len());
+        obus.push((obu_type, &data[pos..end]));
+        pos = end;
+    }
+    obus
+}
+
+fn strip_temporal_delimiters(data: &[u8]) -> Vec<u8> {
+    obus(data)
+        .into_iter()
+        .filter(|&(obu_type, _)| obu_type != 2)
+        .flat_map(|(_, obu)| obu.iter().cloned())
+        .collect()
+}
+
+fn codec_private(codec: Codec, data: &[u8]) -> Option<Vec<u8>> {
+    match codec {
+        Codec::H264 => {
+            let nals = nal_units(data);
+            let sps = nals.iter().find(|nal| nal[0] & 0x1F == 7)?;
+            let pps = nals.iter().find(|nal| nal[0] & 0x1F == 8)?;
+            avc_config(sps, pps)
+        }
+        Codec::HEVC => {
+            let nals = nal_units(data);
+            let find = |nal_type| nals.iter().find(|nal| (nal[0] >> 1) & 0x3F == nal_type);
+            hevc_config(find(32)?, find(33)?, find(34)?)
+        }
+        Codec::AV1 => {
+            let (_, sequence_header) = obus(data).into_iter().find(|&(t, _)| t == 1)?;
+            av1_config(sequence_header)
+        }
+        _ => None,
+    }
+}
+
+/// AVCDecoderConfigurationRecord with 4 byte NAL unit lengths.
+fn avc_config(sps: &[u8], pps: &[u8]) -> Option<Vec<u8>> {
+    if sps.len() < 4 {
+        return None;
+    }
+
+    let mut config = vec![1, sps[1], sps[2], sps[3], 0xFF, 0xE1];
+    config.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    config.extend_from_slice(sps);
+    config.push(1);
+    config.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    config.extend_from_slice(pps);
+
+    Some(config)
+}
+
+/// HEVCDecoderConfigurationRecord with 4 byte NAL unit lengths.
+fn hevc_config(vps: &[u8], sps: &[u8], pps: &[u8]) -> Option<Vec<u8>> {
+    let rbsp = unescape(sps.get(2..)?);
+    let max_sub_layers_minus1 = (rbsp.first()? >> 1) & 0x7;
+    let temporal_id_nested = rbsp[0] & 1;
+    let profile_tier_level = rbsp.get(1..13)?;
+
+    let mut bits = BitReader::new(&rbsp[13..]);
+    let sub_layers = usize::from(max_sub_layers_minus1);
+    let mut present = Vec::with_capacity(sub_layers);
+    for _ in 0..sub_layers {
+        present.push((bits.bit()?, bits.bit()?));
+    }
+    if sub_layers > 0 {
+        bits.skip(2 * (8 - sub_layers))?;
+    }
+    for (profile, level) in present {
+        bits.skip(88 * profile as usize + 8 * level as usize)?;
+    }
+    bits.ue()?;
+    let chroma_format_idc = bits.ue()?;
+    if chroma_format_idc == 3 {
+        bits.skip(1)?;
+    }
+    bits.ue()?;
+    bits.ue()?;
+    if bits.bit()? {
+        for _ in 0..4 {
+            bits.ue()?;
+        }
+    }
+    let bit_depth_luma_minus8 = bits.ue()?;
+    let bit_depth_chroma_minus8 = bits.ue()?;
+
+    let mut config = vec![1];
+    config.extend_from_slice(profile_tier_level);
+    config.extend_from_slice(&[
+        0xF0,
+        0x00,
+        0xFC,
+        0xFC | chroma_format_idc as u8,
+        0xF8 | bit_depth_luma_minus8 as u8,
+        0xF8 | bit_depth_chroma_minus8 as u8,
+        0x00,
+        0x00,
+        ((max_sub_layers_minus1 + 1) << 3) | (temporal_id_nested << 2) | 0x3,
+        3,
+    ]);
+    for (nal_type, nal) in [(32u8, vps), (33, sps), (34, pps)] {
+        config.push(0x80 | nal_type);
+        config.extend_from_slice(&1u16.to_be_bytes());
+        config.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        config.extend_from_slice(nal);
+    }
+
+    Some(config)
+}
+
+/// AV1CodecConfigurationRecord followed by the sequence header OBU.
+fn av1_config(obu: &[u8]) -> Option<Vec<u8>> {
+    let header = *obu.first()?;
+    let mut payload = 1 + usize::from(header & 0x4 != 0);
+    if header & 0x2 != 0 {
+        while obu.get(payload)? & 0x80 != 0 {
+            payload += 1;
+        }
+        payload += 1;
+    }
+    let mut bits = BitReader::new(obu.get(payload..)?);
+
+    let profile = bits.bits(3)?;
+    bits.skip(1)?;
+    let reduced_still_picture_header = bits.bit()?;
+    let (level, tier);
+    let mut frame_ids = false;
+    if reduced_still_picture_header {
+        level = bits.bits(5)?;
+        tier = 0;
+    } else {
+        let mut decoder_model_info = false;
+        let mut buffer_delay_length = 0;
+        if bits.bit()? {
+            bits.skip(64)?;
+            if bits.bit()? {
+                bits.uvlc()?;
+            }
+            decoder_model_info = bits.bit()?;
+            if decoder_model_info {
+                buffer_delay_length = bits.bits(5)? as usize + 1;
+                bits.skip(32 + 5 + 5)?;
+            }
+        }
+        let initial_display_delay = bits.bit()?;
+        let operating_points = bits.bits(5)? + 1;
+        let mut first = None;
+        for _ in 0..operating_points {
+            bits.skip(12)?;
+            let op_level = bits.bits(5)?;
+            let op_tier = if op_level > 7 { bits.bits(1)? } else { 0 };
+            if decoder_model_info && bits.bit()? {
+                bits.skip(2 * buffer_delay_length + 1)?;
+            }
+            if initial_display_delay && bits.bit()? {
+                bits.skip(4)?;
+            }
+            first.get_or_insert((op_level, op_tier));
+        }
+        let (op_level, op_tier) = first?;
+        level = op_level;
+        tier = op_tier;
+    }
+
+    let width_bits = bits.bits(4)? as usize + 1;
+    let height_bits = bits.bits(4)? as usize + 1;
+    bits.skip(width_bits + height_bits)?;
+    if !reduced_still_picture_header {
+        frame_ids = bits.bit()?;
+    }
+    if frame_ids {
+        bits.skip(7)?;
+    }
+    bits.skip(3)?;
+    if !reduced_still_picture_header {
+        bits.skip(4)?;
+        let order_hint = bits.bit()?;
+        if order_hint {
+            bits.skip(2)?;
+        }
+        let force_screen_content_tools = if bits.bit()? { 2 } else { bits.bits(1)? };
+        if force_screen_content_tools > 0 && !bits.bit()? {
+            bits.skip(1)?;
+        }
+        if order_hint {
+            bits.skip(3)?;
+        }
+    }
+    bits.skip(3)?;
+
+    let high_bitdepth = bits.bits(1)?;
+    let twelve_bit = if profile == 2 && high_bitdepth == 1 {
+        bits.bits(1)?
+    } else {
+        0
+    };
+    let monochrome = if profile == 1 { 0 } else { bits.bits(1)? };
+    let (mut primaries, mut transfer, mut matrix) = (2, 2, 2);
+    if bits.bit()? {
+        primaries = bits.bits(8)?;
+        transfer = bits.bits(8)?;
+        matrix = bits.bits(8)?;
+    }
+    let (subsampling_x, subsampling_y, sample_position) = if monochrome == 1 {
+        (1, 1, 0)
+    } else if primaries == 1 && transfer == 13 && matrix == 0 {
+        (0, 0, 0)
+    } else {
+        bits.skip(1)?;
+        let (x, y) = match profile {
+            0 => (1, 1),
+            1 => (0, 0),
+            _ if twelve_bit == 1 => {
+                let x = bits.bits(1)?;
+                (x, if x == 1 { bits.bits(1)? } else { 0 })
+            }
+            _ => (1, 0),
+        };
+        (x, y, if x == 1 && y == 1 { bits.bits(2)? } else { 0 })
+    };
+
+    let mut config = vec![
+        0x81,
+        (profile << 5 | level) as u8,
+        (tier << 7
+            | high_bitdepth << 6
+            | twelve_bit << 5
+            | monochrome << 4
+            | subsampling_x << 3
+            | subsampling_y << 2
+            | sample_position) as u8,
+        0,
+    ];
+    config.extend_from_slice(obu);
+
+    Some(config)
+}
+
+/// Removes the emulation prevention bytes of a NAL unit payload.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = byte >> (7 - self.pos % 8) & 1;
+        self.pos += 1;
+        Some(bit == 1)
+    }
+
+    fn bits(&mut self, n: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = value << 1 | u32::from(self.bit()?);
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.pos += n;
+        if self.pos > self.data.len() * 8 {
+            return None;
+        }
+        Some(())
+    }
+
+    /// Exp-Golomb code of H.264 and HEVC.
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1 << zeros) - 1 + self.bits(zeros)?)
+    }
+
+    /// Variable length code of AV1.
+    fn uvlc(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+            if zeros >= 32 {
+                return Some(u32::MAX);
+            }
+        }
+        Some((1 << zeros) - 1 + self.bits(zeros)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::io::Cursor;
+
+    #[test]
+    fn ebml_sizes() {
+        let mut buf = Vec::new();
+        write_size(&mut buf, 5);
+        write_size(&mut buf, 127);
+        write_size(&mut buf, 300);
+        assert_eq!(buf, [0x85, 0x40, 0x7F, 0x41, 0x2C]);
+        assert_eq!(fixed_size(0x1234), [1, 0, 0, 0, 0, 0, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn annex_b_to_length_prefixed() {
+        let data = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4,
+        ];
+        assert_eq!(
+            nal_units(&data),
+            [&[0x67, 1, 2][..], &[0x68, 3], &[0x65, 4]]
+        );
+        assert_eq!(
+            length_prefixed(&data[7..]),
+            [0, 0, 0, 2, 0x68, 3, 0, 0, 0, 2, 0x65, 4]
+        );
+    }
+
+    #[test]
+    fn h264_file_layout() {
+        let keyframe = [
+            0, 0, 0, 1, 0x67, 0x64, 0x00, 0x28, 0xAC, 0, 0, 0, 1, 0x68, 0xEE, 0x3C, 0x80, 0, 0, 0,
+            1, 0x65, 0x88, 0x84,
+        ];
+        let mut writer =
+            MkvWriter::new(Cursor::new(Vec::new()), Track::new(Codec::H264, 1920, 1080)).unwrap();
+        assert!(writer.write_packet(&[0, 0, 1, 0x41, 1], 0, false).is_err());
+        writer.write_packet(&keyframe, 0, true).unwrap();
+        writer
+            .write_packet(&[0, 0, 1, 0x41, 1], 400_000, false)
+            .unwrap();
+        writer.write_packet(&keyframe, 800_000, true).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let find = |id: &[u8]| file.windows(id.len()).position(|w| w == id);
+        assert_eq!(find(&[0x1A, 0x45, 0xDF, 0xA3]), Some(0));
+        assert!(find(b"V_MPEG4/ISO/AVC").is_some());
+        assert!(find(&[0x63, 0xA2, 0x94, 1, 0x64, 0x00, 0x28, 0xFF, 0xE1]).is_some());
+        assert_eq!(
+            file.windows(4)
+                .filter(|w| *w == [0x1F, 0x43, 0xB6, 0x75])
+                .count(),
+            2
+        );
+
+        // The segment and the cues end where the file ends.
+        let segment = find(&[0x18, 0x53, 0x80, 0x67]).unwrap();
+        let size = u64::from_be_bytes(file[segment + 4..segment + 12].try_into().unwrap());
+        assert_eq!(size & !(1 << 56), (file.len() - segment - 12) as u64);
+        // The first occurrence is the seek head entry.
+        let cues = file
+            .windows(4)
+            .rposition(|w| w == [0x1C, 0x53, 0xBB, 0x6B])
+            .unwrap();
+        assert_eq!(cues + 5 + usize::from(file[cues + 4] & 0x7F), file.len());
+    }
+}
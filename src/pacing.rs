@@ -0,0 +1,195 @@
+//! Releasing frames at the pace given by their timestamps, e.g. to replay a
+//! file as if it were a live source.
+//!
+//! ```no_run
+//! # use nvidia_video_codec::pacing::Pacer;
+//! # fn run(frames: nvidia_video_codec::cuvid::FramesIter) {
+//! for frame in Pacer::new().speed(2.0).pace(frames) {
+//!     // Arrives at twice the real time rate.
+//! }
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use cuvid::decoder::CLOCK_RATE;
+use cuvid::{DeviceFrame, GpuFrame};
+
+/// Anything carrying a presentation timestamp.
+pub trait Timestamped {
+    fn timestamp(&self) -> i64;
+}
+
+impl Timestamped for GpuFrame {
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+impl Timestamped for DeviceFrame {
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+/// Maps timestamps onto the wall clock, starting from the first one seen.
+///
+/// Late items are released immediately without catching up. Timestamps going
+/// backwards or lying more than `MAX_WAIT` in the future restart the clock,
+/// so loops and discontinuities in the source do not stall the output.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    speed: f64,
+    clock_rate: u32,
+    origin: Option<(Instant, i64)>,
+    last: Option<i64>,
+}
+
+const MAX_WAIT: Duration = Duration::from_secs(5);
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Pacer {
+            speed: 1.0,
+            clock_rate: CLOCK_RATE,
+            origin: None,
+            last: None,
+        }
+    }
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Playback speed relative to real time, 1.0 by default.
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "speed must be positive");
+        self.speed = speed;
+        self
+    }
+
+    /// Timestamp units per second, 10 MHz like the decoder by default.
+    pub fn clock_rate(mut self, clock_rate: u32) -> Self {
+        assert!(clock_rate > 0, "clock rate must be positive");
+        self.clock_rate = clock_rate;
+        self
+    }
+
+    /// Restarts the clock at the next timestamp.
+    pub fn reset(&mut self) {
+        self.origin = None;
+        self.last = None;
+    }
+
+    /// When the item stamped `timestamp` is due, measured from `now`.
+    pub fn deadline(&mut self, timestamp: i64, now: Instant) -> Instant {
+        let (start, first) = match self.origin {
+            Some(origin) if self.last.is_none_or(|last| timestamp >= last) => origin,
+            _ => (now, timestamp),
+        };
+
+        let offset = (timestamp - first) as f64 / self.clock_rate as f64 / self.speed;
+        let deadline = start + Duration::from_secs_f64(offset);
+
+        self.last = Some(timestamp);
+        if deadline > now + MAX_WAIT {
+            self.origin = Some((now, timestamp));
+            return now;
+        }
+        self.origin = Some((start, first));
+
+        deadline
+    }
+
+    /// Sleeps until the item stamped `timestamp` is due.
+    pub fn wait(&mut self, timestamp: i64) {
+        let now = Instant::now();
+        let deadline = self.deadline(timestamp, now);
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+
+    pub fn pace<I>(self, iter: I) -> Paced<I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: Timestamped,
+    {
+        Paced {
+            iter: iter.into_iter(),
+            pacer: self,
+        }
+    }
+}
+
+/// An iterator yielding the items of another one once they are due.
+pub struct Paced<I> {
+    iter: I,
+    pacer: Pacer,
+}
+
+impl<I> Paced<I> {
+    pub fn pacer(&mut self) -> &mut Pacer {
+        &mut self.pacer
+    }
+
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<I> Iterator for Paced<I>
+where
+    I: Iterator,
+    I::Item: Timestamped,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.pacer.wait(item.timestamp());
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "clock rate must be positive")]
+    fn zero_clock_rate() {
+        Pacer::new().clock_rate(0);
+    }
+
+    #[test]
+    fn deadlines() {
+        let mut pacer = Pacer::new().speed(2.0);
+        let start = Instant::now();
+
+        assert_eq!(pacer.deadline(1_000_000, start), start);
+        assert_eq!(
+            pacer.deadline(3_000_000, start),
+            start + Duration::from_millis(100)
+        );
+
+        // Late items are due immediately, without moving the clock.
+        let late = start + Duration::from_secs(1);
+        assert!(pacer.deadline(4_000_000, late) < late);
+        assert_eq!(
+            pacer.deadline(21_000_000, late),
+            start + Duration::from_millis(1000)
+        );
+
+        // Going back or far ahead restarts at the current time.
+        assert_eq!(pacer.deadline(0, late), late);
+        assert_eq!(pacer.deadline(1_000_000_000, late), late);
+        assert_eq!(
+            pacer.deadline(1_002_000_000, late),
+            late + Duration::from_millis(100)
+        );
+    }
+}
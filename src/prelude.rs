@@ -0,0 +1,11 @@
+//! The types needed for decoding in most programs.
+//!
+//! ```
+//! use nvidia_video_codec::prelude::*;
+//! ```
+
+pub use cuda::context::CuContext;
+pub use cuvid::{
+    decode, decode_with, Codec, DecodeError, Decoder, DecoderBuilder, DeviceFrame, GpuFrame,
+    Packet, PacketFlags, PacketSource, Packets,
+};
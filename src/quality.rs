@@ -0,0 +1,176 @@
+//! Full reference quality metrics computed on the GPU with NPP.
+//!
+//! All functions expect the CUDA context owning the compared surfaces to be
+//! current on the calling thread, e.g. with `CuContext::push`.
+
+use super::{ffi, NppResult};
+use cuda::mem::DeviceBuffer;
+use cuda::stream::CuStream;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    Cuda(ffi::cuda::CUresult),
+    Npp(ffi::npp::NppStatus),
+}
+
+impl From<ffi::cuda::CUresult> for Error {
+    fn from(res: ffi::cuda::CUresult) -> Self {
+        Error::Cuda(res)
+    }
+}
+
+impl From<ffi::npp::NppStatus> for Error {
+    fn from(status: ffi::npp::NppStatus) -> Self {
+        Error::Npp(status)
+    }
+}
+
+/// A single 8 bit plane in device memory.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub ptr: ffi::cuvid::CUdeviceptr,
+    pub pitch: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Plane {
+    /// The luma plane of an NV12 surface.
+    pub fn nv12_luma(ptr: ffi::cuvid::CUdeviceptr, pitch: i32, width: u32, height: u32) -> Self {
+        Plane {
+            ptr,
+            pitch,
+            width,
+            height,
+        }
+    }
+
+    /// The interleaved UV plane of an NV12 surface, following the luma plane.
+    pub fn nv12_chroma(ptr: ffi::cuvid::CUdeviceptr, pitch: i32, width: u32, height: u32) -> Self {
+        Plane {
+            ptr: ptr + (pitch as u64) * (height as u64),
+            pitch,
+            width,
+            height: (height + 1) / 2,
+        }
+    }
+
+    fn size(&self) -> ffi::npp::NppiSize {
+        ffi::npp::NppiSize {
+            width: self.width as _,
+            height: self.height as _,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    pub psnr_y: f32,
+    pub psnr_uv: f32,
+    pub ssim_y: f32,
+}
+
+fn check_sizes(reference: &Plane, distorted: &Plane) -> Result<(), Error> {
+    if reference.width != distorted.width || reference.height != distorted.height {
+        return Err(Error::Npp(ffi::npp::NppStatus_NPP_SIZE_ERROR));
+    }
+
+    Ok(())
+}
+
+fn read_result(result: &DeviceBuffer, stream: Option<&CuStream>) -> Result<f32, Error> {
+    let mut bytes = [0u8; 4];
+    result.copy_to_host(&mut bytes, stream)?;
+
+    Ok(f32::from_ne_bytes(bytes))
+}
+
+/// Peak signal to noise ratio in dB between two planes of the same size.
+pub fn psnr(reference: &Plane, distorted: &Plane, stream: Option<&CuStream>) -> Result<f32, Error> {
+    check_sizes(reference, distorted)?;
+
+    let stream_ctx = super::npp_stream_context(stream)?;
+    let mut buffer_size = 0;
+    unsafe {
+        ffi::npp::nppiPSNRGetBufferHostSize_8u_C1R_Ctx(
+            reference.size(),
+            &mut buffer_size,
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    let scratch = DeviceBuffer::new(buffer_size as usize)?;
+    let result = DeviceBuffer::new(std::mem::size_of::<f32>())?;
+
+    unsafe {
+        ffi::npp::nppiPSNR_8u_C1R_Ctx(
+            reference.ptr as *const ffi::npp::Npp8u,
+            reference.pitch,
+            distorted.ptr as *const ffi::npp::Npp8u,
+            distorted.pitch,
+            reference.size(),
+            result.as_ptr() as *mut ffi::npp::Npp32f,
+            scratch.as_ptr() as *mut ffi::npp::Npp8u,
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    read_result(&result, stream)
+}
+
+/// Structural similarity index between two planes of the same size.
+pub fn ssim(reference: &Plane, distorted: &Plane, stream: Option<&CuStream>) -> Result<f32, Error> {
+    check_sizes(reference, distorted)?;
+
+    let stream_ctx = super::npp_stream_context(stream)?;
+    let mut buffer_size = 0;
+    unsafe {
+        ffi::npp::nppiSSIMGetBufferHostSize_8u_C1R_Ctx(
+            reference.size(),
+            &mut buffer_size,
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    let scratch = DeviceBuffer::new(buffer_size as usize)?;
+    let result = DeviceBuffer::new(std::mem::size_of::<f32>())?;
+
+    unsafe {
+        ffi::npp::nppiSSIM_8u_C1R_Ctx(
+            reference.ptr as *const ffi::npp::Npp8u,
+            reference.pitch,
+            distorted.ptr as *const ffi::npp::Npp8u,
+            distorted.pitch,
+            reference.size(),
+            result.as_ptr() as *mut ffi::npp::Npp32f,
+            scratch.as_ptr() as *mut ffi::npp::Npp8u,
+            stream_ctx,
+        )
+        .err()?;
+    }
+
+    read_result(&result, stream)
+}
+
+/// Luma/chroma PSNR and luma SSIM between two NV12 surfaces.
+pub fn compare_nv12(
+    reference: (ffi::cuvid::CUdeviceptr, i32),
+    distorted: (ffi::cuvid::CUdeviceptr, i32),
+    width: u32,
+    height: u32,
+    stream: Option<&CuStream>,
+) -> Result<Metrics, Error> {
+    let ref_y = Plane::nv12_luma(reference.0, reference.1, width, height);
+    let dist_y = Plane::nv12_luma(distorted.0, distorted.1, width, height);
+    let ref_uv = Plane::nv12_chroma(reference.0, reference.1, width, height);
+    let dist_uv = Plane::nv12_chroma(distorted.0, distorted.1, width, height);
+
+    Ok(Metrics {
+        psnr_y: psnr(&ref_y, &dist_y, stream)?,
+        psnr_uv: psnr(&ref_uv, &dist_uv, stream)?,
+        ssim_y: ssim(&ref_y, &dist_y, stream)?,
+    })
+}
@@ -0,0 +1,261 @@
+//! Export of timestamp aligned reference/distorted frame pairs in the planar
+//! 8 bit YUV 4:2:0 layout consumed by libvmaf and the `vmaf` tool.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{ffi, CudaResult};
+
+/// A host side I420 frame: the Y plane followed by the U and V planes.
+#[derive(Clone, Debug)]
+pub struct YuvFrame {
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: i64,
+    pub data: Vec<u8>,
+}
+
+impl YuvFrame {
+    fn chroma_size(&self) -> (usize, usize) {
+        (
+            ((self.width + 1) / 2) as usize,
+            ((self.height + 1) / 2) as usize,
+        )
+    }
+
+    pub fn y(&self) -> &[u8] {
+        &self.data[..(self.width * self.height) as usize]
+    }
+
+    pub fn u(&self) -> &[u8] {
+        let luma = (self.width * self.height) as usize;
+        let (cw, ch) = self.chroma_size();
+        &self.data[luma..luma + cw * ch]
+    }
+
+    pub fn v(&self) -> &[u8] {
+        let luma = (self.width * self.height) as usize;
+        let (cw, ch) = self.chroma_size();
+        &self.data[luma + cw * ch..]
+    }
+
+    /// Downloads an NV12 surface and deinterleaves its chroma plane.
+    ///
+    /// The context owning the surface must be current on the calling thread.
+    pub fn from_nv12(
+        ptr: ffi::cuvid::CUdeviceptr,
+        pitch: u32,
+        width: u32,
+        height: u32,
+        timestamp: i64,
+    ) -> Result<Self, ffi::cuda::CUresult> {
+        let chroma_height = (height + 1) / 2;
+        // A chroma row holds a U and a V sample for every two pixels, one
+        // more byte than the luma row for odd widths.
+        let width_bytes = ((width + 1) & !1) as usize;
+        let mut nv12 = vec![0u8; width_bytes * (height + chroma_height) as usize];
+
+        let mut copy: ffi::cuda::CUDA_MEMCPY2D = unsafe { std::mem::zeroed() };
+        copy.srcMemoryType = ffi::cuda::CUmemorytype_enum_CU_MEMORYTYPE_DEVICE;
+        copy.srcDevice = ptr;
+        copy.srcPitch = pitch as _;
+        copy.dstMemoryType = ffi::cuda::CUmemorytype_enum_CU_MEMORYTYPE_HOST;
+        copy.dstHost = nv12.as_mut_ptr() as *mut std::os::raw::c_void;
+        copy.dstPitch = width_bytes as _;
+        copy.WidthInBytes = width_bytes as _;
+        copy.Height = (height + chroma_height) as _;
+
+        unsafe { ffi::cuda::cuMemcpy2D_v2(&copy).err()? };
+
+        Ok(Self::deinterleave(&nv12, width, height, timestamp))
+    }
+
+    /// Splits NV12 rows of `(width + 1) & !1` bytes into I420 planes.
+    fn deinterleave(nv12: &[u8], width: u32, height: u32, timestamp: i64) -> Self {
+        let width_bytes = ((width + 1) & !1) as usize;
+        let mut frame = YuvFrame {
+            width,
+            height,
+            timestamp,
+            data: Vec::with_capacity(nv12.len()),
+        };
+        let (luma, chroma) = nv12.split_at(width_bytes * height as usize);
+        let (cw, _) = frame.chroma_size();
+
+        for row in luma.chunks(width_bytes) {
+            frame.data.extend_from_slice(&row[..width as usize]);
+        }
+        for row in chroma.chunks(width_bytes) {
+            frame.data.extend(row.iter().step_by(2).take(cw));
+        }
+        for row in chroma.chunks(width_bytes) {
+            frame.data.extend(row.iter().skip(1).step_by(2).take(cw));
+        }
+
+        frame
+    }
+}
+
+/// Receives the aligned frame pairs.
+pub trait PairSink {
+    fn write_pair(&mut self, reference: &YuvFrame, distorted: &YuvFrame) -> io::Result<()>;
+}
+
+impl<F> PairSink for F
+where
+    F: FnMut(&YuvFrame, &YuvFrame) -> io::Result<()>,
+{
+    fn write_pair(&mut self, reference: &YuvFrame, distorted: &YuvFrame) -> io::Result<()> {
+        self(reference, distorted)
+    }
+}
+
+/// Writes the pairs as two raw `.yuv` files, as taken by `vmaf -r ... -d ...`.
+pub struct YuvFiles {
+    reference: BufWriter<File>,
+    distorted: BufWriter<File>,
+}
+
+impl YuvFiles {
+    pub fn create<P: AsRef<Path>, Q: AsRef<Path>>(reference: P, distorted: Q) -> io::Result<Self> {
+        Ok(YuvFiles {
+            reference: BufWriter::new(File::create(reference)?),
+            distorted: BufWriter::new(File::create(distorted)?),
+        })
+    }
+}
+
+impl PairSink for YuvFiles {
+    fn write_pair(&mut self, reference: &YuvFrame, distorted: &YuvFrame) -> io::Result<()> {
+        self.reference.write_all(&reference.data)?;
+        self.distorted.write_all(&distorted.data)
+    }
+}
+
+impl Drop for YuvFiles {
+    fn drop(&mut self) {
+        let _ = self.reference.flush();
+        let _ = self.distorted.flush();
+    }
+}
+
+/// Pairs reference and distorted frames by timestamp and hands them to a sink.
+///
+/// Frames may be pushed in any interleaving as long as each side is in
+/// ascending timestamp order; frames without a counterpart are discarded.
+pub struct VmafExport<S> {
+    sink: S,
+    reference: VecDeque<YuvFrame>,
+    distorted: VecDeque<YuvFrame>,
+    pairs: u64,
+    unmatched: u64,
+}
+
+impl<S: PairSink> VmafExport<S> {
+    pub fn new(sink: S) -> Self {
+        VmafExport {
+            sink,
+            reference: VecDeque::new(),
+            distorted: VecDeque::new(),
+            pairs: 0,
+            unmatched: 0,
+        }
+    }
+
+    pub fn push_reference(&mut self, frame: YuvFrame) -> io::Result<()> {
+        self.reference.push_back(frame);
+        self.drain()
+    }
+
+    pub fn push_distorted(&mut self, frame: YuvFrame) -> io::Result<()> {
+        self.distorted.push_back(frame);
+        self.drain()
+    }
+
+    /// Number of pairs written so far.
+    pub fn pairs(&self) -> u64 {
+        self.pairs
+    }
+
+    /// Number of frames discarded for lack of a counterpart.
+    pub fn unmatched(&self) -> u64 {
+        self.unmatched
+    }
+
+    /// Discards the frames still waiting for a counterpart and returns the sink.
+    pub fn finish(mut self) -> S {
+        self.unmatched += (self.reference.len() + self.distorted.len()) as u64;
+        self.sink
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        loop {
+            let (r, d) = match (self.reference.front(), self.distorted.front()) {
+                (Some(r), Some(d)) => (r.timestamp, d.timestamp),
+                _ => return Ok(()),
+            };
+
+            if r < d {
+                self.reference.pop_front();
+                self.unmatched += 1;
+            } else if d < r {
+                self.distorted.pop_front();
+                self.unmatched += 1;
+            } else {
+                let reference = self.reference.pop_front().unwrap();
+                let distorted = self.distorted.pop_front().unwrap();
+                self.sink.write_pair(&reference, &distorted)?;
+                self.pairs += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: i64) -> YuvFrame {
+        YuvFrame {
+            width: 2,
+            height: 2,
+            timestamp,
+            data: vec![0; 6],
+        }
+    }
+
+    #[test]
+    fn odd_width_planes() {
+        // 3x2 luma in rows of 4 bytes, then one row of U V U V.
+        let nv12 = [1, 2, 3, 0, 4, 5, 6, 0, 10, 20, 11, 21];
+        let frame = YuvFrame::deinterleave(&nv12, 3, 2, 0);
+        assert_eq!(frame.y(), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(frame.u(), [10, 11]);
+        assert_eq!(frame.v(), [20, 21]);
+    }
+
+    #[test]
+    fn pairs_by_timestamp() {
+        let mut seen = Vec::new();
+        {
+            let mut export = VmafExport::new(|r: &YuvFrame, d: &YuvFrame| {
+                seen.push((r.timestamp, d.timestamp));
+                Ok(())
+            });
+
+            export.push_reference(frame(0)).unwrap();
+            export.push_reference(frame(1)).unwrap();
+            export.push_reference(frame(2)).unwrap();
+            export.push_distorted(frame(1)).unwrap();
+            export.push_distorted(frame(2)).unwrap();
+            export.push_distorted(frame(3)).unwrap();
+
+            assert_eq!(export.pairs(), 2);
+            assert_eq!(export.unmatched(), 1);
+        }
+
+        assert_eq!(seen, vec![(1, 1), (2, 2)]);
+    }
+}